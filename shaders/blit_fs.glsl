@@ -0,0 +1,9 @@
+in vec2 pass_uv;
+
+out vec4 out_color;
+
+uniform sampler2D source;
+
+void main() {
+    out_color = texture(source, pass_uv);
+}
@@ -0,0 +1,10 @@
+out vec2 pass_uv;
+
+void main() {
+    // A fullscreen quad drawn as a triangle strip,
+    // with corners derived from the vertex index.
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+    
+    pass_uv = corner;
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -1,10 +1,120 @@
 in vec2 pass_uv;
+flat in vec2 pass_tile;
 in vec3 color;
+flat in uint pass_block_id;
+flat in uint pass_anim;
+flat in uint pass_blend_tile;
+in float pass_blend_weight;
+in vec3 pass_light;
+in float pass_sky;
+in float pass_ao;
+in float pass_depth;
 
 out vec4 out_color;
 
 uniform sampler2D terrain_tex;
+uniform float texel_lod_bias;
+uniform float time;
+uniform float opacity;
+uniform vec3 fog_color;
+uniform float fog_distance;
+
+// Block ids, matching `Block` in `voxel.rs`.
+const uint WATER = 6u;
+
+const float WATER_SCROLL_SPEED = 0.25;
+
+// How dark a fully occluded corner gets.
+const float AO_MIN = 0.4;
+
+// How dark a face without any sky light gets.
+const float SKY_MIN = 0.15;
+
+// The fraction of `fog_distance` where fog begins.
+const float FOG_START = 0.6;
+
+// Sample the atlas. When `texel_lod_bias` is positive, fragments
+// covering more than one texel (scaled by the bias) average four
+// taps across their footprint to reduce shimmer at a distance.
+// This costs three extra texture fetches for those fragments only.
+vec4 sample_terrain(vec2 uv) {
+    vec2 footprint = fwidth(pass_uv);
+    vec2 size = vec2(textureSize(terrain_tex, 0));
+    float texels = max(footprint.x * size.x, footprint.y * size.y);
+    
+    if (texel_lod_bias <= 0.0 || texels * texel_lod_bias < 1.0) {
+        return texture(terrain_tex, uv);
+    }
+    
+    vec2 d = footprint * 0.25;
+    return 0.25 * (texture(terrain_tex, uv + vec2(-d.x, -d.y)) +
+                   texture(terrain_tex, uv + vec2( d.x, -d.y)) +
+                   texture(terrain_tex, uv + vec2(-d.x,  d.y)) +
+                   texture(terrain_tex, uv + vec2( d.x,  d.y)));
+}
+
+// Wrap the UV back into its tile, so faces merged by greedy meshing
+// repeat the tile across their span. `pass_tile` is the tile's top
+// left corner, taken from the provoking vertex.
+vec2 tile_uv() {
+    vec2 tile_size = 16.0 / vec2(textureSize(terrain_tex, 0));
+    
+    return pass_tile + mod(pass_uv - pass_tile, tile_size);
+}
+
+// Apply per-block UV animation. Scrolling V wraps within the tile.
+//
+// Multi-frame blocks step U along the atlas row, since their
+// frames are consecutive tiles. See `Animation::pack` for the
+// layout of `pass_anim`.
+vec2 animate_uv() {
+    vec2 size = vec2(textureSize(terrain_tex, 0));
+    vec2 uv = tile_uv();
+    
+    uint frames = pass_anim & 0xFFu;
+    if (frames > 1u) {
+        float frame_time = float(pass_anim >> 8) / 1000.0;
+        uint frame = uint(time / frame_time) % frames;
+        uv.x += float(frame) * 16.0 / size.x;
+    }
+    
+    if (pass_block_id == WATER) {
+        float tile_v = 16.0 / size.y;
+        uv.y = pass_tile.y + fract((uv.y - pass_tile.y) / tile_v + time * WATER_SCROLL_SPEED) * tile_v;
+    }
+    
+    return uv;
+}
+
+// Sample the same spot of the neighbor's tile, for edge blending.
+// `pass_blend_tile` is the tile index plus one, counting along the
+// rows. This is the one extra fetch blending costs, per blended fragment.
+vec4 sample_blend() {
+    ivec2 size = textureSize(terrain_tex, 0);
+    vec2 tile_size = 16.0 / vec2(size);
+    uint columns = uint(size.x) / 16u;
+    uint index = pass_blend_tile - 1u;
+    
+    vec2 tile = vec2(float(index % columns), float(index / columns));
+    return sample_terrain((tile + fract(pass_uv / tile_size)) * tile_size);
+}
 
 void main() {
-   out_color = vec4(color, 1.0) * texture(terrain_tex, pass_uv);
+    vec4 texel = sample_terrain(animate_uv());
+    
+    if (pass_blend_tile != 0u) {
+        texel = mix(texel, sample_blend(), pass_blend_weight);
+    }
+    
+    // Sky light scales the face shading, colored block light adds
+    // to it, and ambient occlusion darkens enclosed corners.
+    vec3 sky = color * mix(SKY_MIN, 1.0, pass_sky);
+    vec3 shade = min(sky + pass_light, vec3(1.0)) * mix(AO_MIN, 1.0, pass_ao);
+    out_color = vec4(shade, opacity) * texel;
+    
+    // Fade toward the fog color with distance from the camera.
+    if (fog_distance > 0.0) {
+        float fog = smoothstep(FOG_START * fog_distance, fog_distance, pass_depth);
+        out_color.rgb = mix(out_color.rgb, fog_color, fog);
+    }
 }
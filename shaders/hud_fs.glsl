@@ -0,0 +1,19 @@
+in vec2 pass_uv;
+
+out vec4 out_color;
+
+uniform sampler2D hud_tex;
+
+// The HUD image has no alpha channel, so
+// texels darker than this are left out.
+const float CUTOFF = 0.1;
+
+void main() {
+    vec3 color = texture(hud_tex, pass_uv).rgb;
+    
+    if (max(color.r, max(color.g, color.b)) < CUTOFF) {
+        discard;
+    }
+    
+    out_color = vec4(color, 1.0);
+}
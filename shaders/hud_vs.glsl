@@ -0,0 +1,14 @@
+out vec2 pass_uv;
+
+uniform mat4 projection;
+uniform vec2 size;
+
+void main() {
+    // A quad centered on the origin, drawn as a triangle strip,
+    // with corners derived from the vertex index.
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+    
+    // Images are stored top row first.
+    pass_uv = vec2(corner.x, 1.0 - corner.y);
+    gl_Position = projection * vec4((corner - 0.5) * size, 0.0, 1.0);
+}
@@ -0,0 +1,28 @@
+in vec4 pass_clip;
+
+out vec4 out_color;
+
+uniform mat4 inverse_view_projection;
+uniform vec3 horizon_color;
+uniform vec3 zenith_color;
+uniform vec3 sun_direction;
+uniform float sun_size;
+uniform vec3 sun_color;
+
+// How far the glow reaches, in sun radii.
+const float GLOW_RADII = 8.0;
+
+void main() {
+    // The view ray through this fragment, from unprojecting
+    // a point on the far plane.
+    vec4 far = inverse_view_projection * pass_clip;
+    vec3 ray = normalize(far.xyz / far.w);
+    
+    vec3 sky = mix(horizon_color, zenith_color, clamp(ray.y, 0.0, 1.0));
+    
+    float angle = acos(clamp(dot(ray, sun_direction), -1.0, 1.0));
+    float disc = 1.0 - smoothstep(sun_size * 0.9, sun_size, angle);
+    float glow = pow(max(1.0 - angle / (sun_size * GLOW_RADII), 0.0), 3.0) * 0.5;
+    
+    out_color = vec4(sky + sun_color * max(disc, glow), 1.0);
+}
@@ -0,0 +1,10 @@
+out vec4 pass_clip;
+
+void main() {
+    // A fullscreen quad drawn as a triangle strip,
+    // with corners derived from the vertex index.
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1)) * 2.0 - 1.0;
+    
+    pass_clip = vec4(corner, 1.0, 1.0);
+    gl_Position = vec4(corner, 0.0, 1.0);
+}
@@ -0,0 +1,9 @@
+in vec2 pass_uv;
+
+out vec4 out_color;
+
+uniform sampler2D skybox_tex;
+
+void main() {
+    out_color = vec4(texture(skybox_tex, pass_uv).rgb, 1.0);
+}
@@ -0,0 +1,47 @@
+out vec2 pass_uv;
+
+uniform mat4 view_projection;
+
+// The faces of the cube in the order they appear along the
+// skybox image: +X, -X, +Y, -Y, +Z and -Z. Each has its normal
+// and the directions of its image's right and up, as seen
+// from inside the cube.
+const vec3 NORMALS[6] = vec3[6](
+    vec3(1.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0)
+);
+
+const vec3 RIGHTS[6] = vec3[6](
+    vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0),
+    vec3(1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0),
+    vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)
+);
+
+const vec3 UPS[6] = vec3[6](
+    vec3(0.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0)
+);
+
+// The two triangles of a face, counterclockwise from inside.
+const vec2 CORNERS[6] = vec2[6](
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main() {
+    // Six vertices per face, derived from the vertex index.
+    int face = gl_VertexID / 6;
+    vec2 corner = CORNERS[gl_VertexID % 6];
+    
+    vec3 pos = NORMALS[face]
+             + (corner.x * 2.0 - 1.0) * RIGHTS[face]
+             + (corner.y * 2.0 - 1.0) * UPS[face];
+    
+    // Images are stored top row first.
+    pass_uv = vec2((float(face) + corner.x) / 6.0, 1.0 - corner.y);
+    
+    // Put the cube at the far plane, behind everything.
+    gl_Position = (view_projection * vec4(pos, 1.0)).xyww;
+}
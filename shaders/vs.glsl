@@ -1,40 +1,64 @@
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 uv;
 layout (location = 2) in uint facenum;
+layout (location = 3) in uint block_id;
+layout (location = 4) in uint anim;
+layout (location = 5) in uint blend_tile;
+layout (location = 6) in float blend_weight;
+layout (location = 7) in vec3 light;
+layout (location = 8) in float sky;
+layout (location = 9) in float ao;
 
 out vec2 pass_uv;
+flat out vec2 pass_tile;
 out vec3 color;
+flat out uint pass_block_id;
+flat out uint pass_anim;
+flat out uint pass_blend_tile;
+out float pass_blend_weight;
+out vec3 pass_light;
+out float pass_sky;
+out float pass_ao;
+out float pass_depth;
 
 uniform mat4 model_matrix;
 uniform mat4 view_matrix;
 uniform mat4 projection_matrix;
+uniform vec3 sun_direction;
+
+// The light a face turned away from the sun still gets.
+const float AMBIENT = 0.6;
+
+// The normal of each face number, matching `Face::normal`.
+const vec3 NORMALS[6] = vec3[6](
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0)
+);
 
 void main() {
-    gl_Position = projection_matrix * view_matrix * model_matrix * vec4(pos, 1.0);
+    vec4 view_pos = view_matrix * model_matrix * vec4(pos, 1.0);
+    gl_Position = projection_matrix * view_pos;
     
     pass_uv = uv;
+    pass_tile = uv;
+    pass_block_id = block_id;
+    pass_anim = anim;
+    pass_blend_tile = blend_tile;
+    pass_blend_weight = blend_weight;
+    pass_light = light;
+    pass_sky = sky;
+    pass_ao = ao;
+    pass_depth = length(view_pos.xyz);
     
-    switch (facenum) {
-        case 0u:
-            color = vec3(0.8, 0.8, 0.8);
-            break;
-        case 1u:
-            color = vec3(1.0, 1.0, 1.0);
-            break;
-        case 2u:
-            color = vec3(0.9, 0.9, 0.9);
-            break;
-        case 3u:
-            color = vec3(0.7, 0.7, 0.7);
-            break;
-        case 4u:
-            color = vec3(0.8, 0.8, 0.8);
-            break;
-        case 5u:
-            color = vec3(0.8, 0.8, 0.8);
-            break;
-        default:
-            color = vec3(1.0, 0.0, 0.0);
-            break;
+    if (facenum < 6u) {
+        // Lambert shading against the sun, on top of the ambient light.
+        float diffuse = max(dot(NORMALS[facenum], normalize(sun_direction)), 0.0);
+        color = vec3(AMBIENT + (1.0 - AMBIENT) * diffuse);
+    } else {
+        color = vec3(1.0, 0.0, 0.0);
     }
 }
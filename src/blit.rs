@@ -0,0 +1,111 @@
+//! Rendering to an offscreen target at a reduced resolution,
+//! then stretching the result over the window.
+
+use luminance::framebuffer::Framebuffer;
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::pixel::{Depth32F, RGBA32F};
+use luminance::shader::program::Program;
+use luminance::tess::{Mode, Tess};
+use luminance::texture::{Dim2, Flat};
+use shader;
+
+const CLEAR_COLOR: [f32; 4] = [0., 0., 0., 1.];
+
+/// The smallest supported render scale.
+pub const MIN_SCALE: f32 = 0.1;
+
+/// An offscreen framebuffer that is some fraction of the window's size.
+pub struct ScaledTarget {
+    framebuffer: Framebuffer<Flat, Dim2, RGBA32F, Depth32F>,
+    program: Program<(), (), ()>,
+    quad: Tess<()>,
+    scale: f32,
+    size: [u32; 2],
+}
+
+impl ScaledTarget {
+    /// Create a target `scale` times the size of the window in each
+    /// dimension. The scale is clamped to `MIN_SCALE..1`.
+    /// # Panics
+    /// Panics if the framebuffer or shaders can not be created.
+    pub fn new(window_size: (u32, u32), scale: f32) -> ScaledTarget {
+        let (vs, fs) = shader::BLIT.text().unwrap();
+        let (program, warnings) = Program::from_strings(None, &vs, None, &fs).unwrap();
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        let scale = scale.max(MIN_SCALE).min(1.);
+        let size = Self::scaled_size(window_size, scale);
+        
+        ScaledTarget {
+            framebuffer: Framebuffer::new(size, 0).unwrap(),
+            program,
+            quad: Tess::attributeless(Mode::TriangleStrip, 4),
+            scale,
+            size,
+        }
+    }
+    
+    /// Get the offscreen framebuffer to render into.
+    pub fn framebuffer(&self) -> &Framebuffer<Flat, Dim2, RGBA32F, Depth32F> {
+        &self.framebuffer
+    }
+    
+    /// Get the render scale.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+    
+    /// Change the render scale, recreating the framebuffer.
+    /// # Panics
+    /// Panics if the framebuffer can not be created.
+    pub fn set_scale(&mut self, window_size: (u32, u32), scale: f32) {
+        self.scale = scale.max(MIN_SCALE).min(1.);
+        self.size = Self::scaled_size(window_size, self.scale);
+        self.framebuffer = Framebuffer::new(self.size, 0).unwrap();
+    }
+    
+    /// Read back the depth of the last frame at the center of
+    /// the target, in the range `0..1`. A depth of `1` means
+    /// nothing was drawn there.
+    ///
+    /// This stalls until the GPU has finished the frame, so it
+    /// should not be called every frame.
+    pub fn depth_at_center(&self) -> f32 {
+        let (w, h) = (self.size[0] as usize, self.size[1] as usize);
+        let depths = self.framebuffer.depth_slot().get_raw_texels();
+        
+        depths.get((h / 2) * w + w / 2).cloned().unwrap_or(1.)
+    }
+    
+    /// Stretch the offscreen image over the whole window. Like
+    /// `Drawable::render`, this does not present the frame.
+    pub fn blit(&self, window: &Framebuffer<Flat, Dim2, (), ()>) {
+        self.blit_with(window, |_, _| {});
+    }
+    
+    /// Like `blit`, but draw `overlay` over the image at the
+    /// full resolution of the window, in the same pipeline.
+    pub fn blit_with<F>(&self, window: &Framebuffer<Flat, Dim2, (), ()>, overlay: F)
+        where F: FnOnce(&Gpu, &ShadingGate) {
+        entry(|gpu| {
+            gpu.bind_texture(self.framebuffer.color_slot());
+            
+            pipeline(window, CLEAR_COLOR, |shade_gate| {
+                shade_gate.shade(&self.program, |render_gate, _| {
+                    render_gate.render(RenderState::default(), |tess_gate| {
+                        tess_gate.render((&self.quad).into());
+                    });
+                });
+                
+                overlay(&gpu, &shade_gate);
+            });
+        });
+    }
+    
+    fn scaled_size(window_size: (u32, u32), scale: f32) -> [u32; 2] {
+        [((window_size.0 as f32 * scale) as u32).max(1),
+         ((window_size.1 as f32 * scale) as u32).max(1)]
+    }
+}
@@ -1,13 +1,27 @@
 //! The first person camera is in this module.
 
-use std::f32::consts::PI;
 use luminance::linear::M44;
-use maths::{self, Frustum, Projection, Rotation, ToMatrix, Translation};
+use std::f32::consts::{FRAC_PI_2, PI};
+use maths::{self, Frustum, Ortho, Projection, Ray, Rotation, ToMatrix, Translation, Vec3};
+
+/// The furthest the camera may look up or down, in radians.
+/// This stops just short of straight up, where the view would flip.
+pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.001;
+
+/// The vertical field of view of a new camera, in radians.
+pub const DEFAULT_FOV: f32 = 40. * PI / 180.;
+
+/// The narrowest vertical field of view, in radians.
+pub const MIN_FOV: f32 = 10. * PI / 180.;
+
+/// The widest vertical field of view, in radians.
+pub const MAX_FOV: f32 = 120. * PI / 180.;
 
 /// A first person camera that moves, rotates along X and Y,
 /// and manages the projection matrix.
 pub struct Camera {
-    _projection: Projection,
+    projection: Projection,
+    mode: ProjectionMode,
     projection_matrix: M44,
     pos: Translation,
     rot: Rotation,
@@ -16,13 +30,14 @@ pub struct Camera {
 impl Camera {
     /// Creates a camera centered at the origin (0, 0, 0).
     pub fn new(window_size: (u32, u32)) -> Camera {
-        let projection = Projection::new(40. * (PI / 180.),
+        let projection = Projection::new(DEFAULT_FOV,
                                          window_size.0 as f32 / window_size.1 as f32,
                                          0.1, 1000.0);
         let projection_matrix = projection.to_matrix();
         
         Camera {
-            _projection: projection,
+            projection,
+            mode: ProjectionMode::Perspective,
             projection_matrix,
             pos: Translation::new(0., 0., 0.,),
             rot: Rotation::new(0., 0.),
@@ -35,6 +50,58 @@ impl Camera {
         &self.projection_matrix
     }
     
+    /// Return the current kind of projection.
+    pub fn projection_mode(&self) -> ProjectionMode {
+        self.mode
+    }
+    
+    /// Change the aspect ratio of the projection to match a window
+    /// of this size, such as after it is resized. A zero size, as
+    /// reported for a minimized window, is ignored.
+    pub fn set_aspect(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        
+        self.projection.aspect = width as f32 / height as f32;
+        self.update_projection();
+    }
+    
+    /// Set the vertical field of view of the perspective projection,
+    /// **in radians**, clamped between `MIN_FOV` and `MAX_FOV`.
+    /// Narrowing it zooms in, and also narrows the frustum.
+    pub fn set_fov(&mut self, fov: f32) {
+        self.projection.fov = fov.max(MIN_FOV).min(MAX_FOV);
+        self.update_projection();
+    }
+    
+    /// Get the vertical field of view, **in radians**.
+    pub fn fov(&self) -> f32 {
+        self.projection.fov
+    }
+    
+    /// Switch between perspective and orthographic projection.
+    /// The orthographic projection shares the clipping
+    /// planes and aspect ratio of the perspective one.
+    pub fn set_projection_mode(&mut self, mode: ProjectionMode) {
+        self.mode = mode;
+        self.update_projection();
+    }
+    
+    // Recalculate the cached projection matrix.
+    fn update_projection(&mut self) {
+        let p = &self.projection;
+        
+        self.projection_matrix = match self.mode {
+            ProjectionMode::Perspective => p.to_matrix(),
+            ProjectionMode::Orthographic { scale } => {
+                let (w, h) = (scale * p.aspect, scale);
+                
+                Ortho::new(-w, w, -h, h, p.near, p.far).to_matrix()
+            },
+        };
+    }
+    
     /// Allows reading of the camera's translation.
     pub fn translation(&self) -> &Translation {
         &self.pos
@@ -55,14 +122,48 @@ impl Camera {
         &mut self.rot
     }
     
+    /// Adjust the rotation by this offset, like `Rotation::spin`,
+    /// but keep the pitch within `MAX_PITCH` of level so the view
+    /// never turns upside down.
+    pub fn spin(&mut self, x: f32, y: f32) {
+        self.rot.spin(x, y);
+        self.rot.x = self.rot.x.max(-MAX_PITCH).min(MAX_PITCH);
+    }
+    
+    /// Turn the camera to face `target`, keeping its position.
+    /// Like `spin`, the pitch is kept within `MAX_PITCH` of level.
+    /// A target at the camera's position is ignored.
+    pub fn look_at(&mut self, target: Vec3) {
+        let dir = (target - Vec3::from(&self.pos)).normalize();
+        if dir.length_squared() == 0. {
+            return;
+        }
+        
+        // The inverse of `forward`.
+        self.rot.x = dir.y.asin().max(-MAX_PITCH).min(MAX_PITCH);
+        self.rot.y = (-dir.x).atan2(-dir.z);
+    }
+    
+    /// Get the unit vector the camera is looking along.
+    pub fn forward(&self) -> Vec3 {
+        let (rx, ry) = (self.rot.x, self.rot.y);
+        
+        Vec3::new(-ry.sin() * rx.cos(), rx.sin(), -ry.cos() * rx.cos())
+    }
+    
+    /// Get the ray from the camera's position along the direction
+    /// it is looking, for picking what is at the center of the view.
+    pub fn look_ray(&self) -> Ray {
+        Ray::new(Vec3::from(&self.pos), self.forward())
+    }
+    
     /// Calculate the frustum of the camera. Somewhat expensive.
     pub fn frustum(&self) -> Frustum {
-        Frustum::new(&self.projection_matrix, &self.to_matrix())
+        Frustum::from_camera(self)
     }
     
     /// Move the camera based on the current direction.
     pub fn move_dir(&mut self, dir: MovementDirection, distance: f32) {
-        use ::std::f32::consts::FRAC_PI_2;
         use self::MovementDirection::*;
         
         match dir {
@@ -107,6 +208,17 @@ impl ToMatrix for Camera {
     }
 }
 
+/// The kind of projection used by a `Camera`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionMode {
+    /// The usual 3D perspective projection.
+    Perspective,
+    
+    /// A parallel projection. `scale` is half the height
+    /// of the view, in world units.
+    Orthographic { scale: f32 },
+}
+
 /// Represents the direction of movement for the camera.
 #[derive(Debug, Clone, Copy)]
 pub enum MovementDirection {
@@ -115,3 +227,68 @@ pub enum MovementDirection {
     Left,
     Right,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn spin_clamps_pitch() {
+        let mut camera = Camera::new((800, 600));
+        
+        camera.spin(10., 0.);
+        assert_eq!(camera.rotation().x, MAX_PITCH);
+        
+        camera.spin(-20., 0.);
+        assert_eq!(camera.rotation().x, -MAX_PITCH);
+        
+        camera.spin(0.5, 0.);
+        assert!((camera.rotation().x - (0.5 - MAX_PITCH)).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn look_at_faces_target() {
+        let mut camera = Camera::new((800, 600));
+        camera.translation_mut().slide(1., 2., 3.);
+        
+        for &target in &[Vec3::new(10., 2., 3.), Vec3::new(-4., 7., -9.), Vec3::new(1., 0., 8.)] {
+            camera.look_at(target);
+            
+            let expected = (target - Vec3::from(camera.translation())).normalize();
+            let forward = camera.forward();
+            assert!((forward - expected).length() < 1e-5, "{:?} != {:?}", forward, expected);
+        }
+    }
+    
+    #[test]
+    fn look_at_straight_up_clamps_pitch() {
+        let mut camera = Camera::new((800, 600));
+        camera.look_at(Vec3::new(0., 10., 0.));
+        
+        assert_eq!(camera.rotation().x, MAX_PITCH);
+    }
+    
+    #[test]
+    fn set_fov_updates_projection() {
+        let mut camera = Camera::new((800, 600));
+        
+        for &fov in &[MIN_FOV, 0.5, 1., MAX_FOV] {
+            camera.set_fov(fov);
+            
+            let expected = 1. / (fov / 2.).tan();
+            assert_eq!(camera.fov(), fov);
+            assert!((camera.projection_matrix()[1][1] - expected).abs() < 1e-5);
+        }
+    }
+    
+    #[test]
+    fn set_fov_clamps() {
+        let mut camera = Camera::new((800, 600));
+        
+        camera.set_fov(0.);
+        assert_eq!(camera.fov(), MIN_FOV);
+        
+        camera.set_fov(PI);
+        assert_eq!(camera.fov(), MAX_FOV);
+    }
+}
@@ -0,0 +1,134 @@
+//! A 2D overlay drawn over the scene, such as the crosshair.
+
+use std::rc::Rc;
+use luminance::depth_test::DepthTest;
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
+use luminance::linear::M44;
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::pixel::RGB32F;
+use luminance::shader::program::{Program, ProgramError, Uniform, UniformBuilder,
+                                 UniformInterface, UniformWarning};
+use luminance::tess::{Mode, Tess};
+use luminance::texture::{Dim2, Flat, Texture};
+use png::OutputInfo;
+use camera::Camera;
+use maths::{Ortho, ToMatrix};
+use model::Drawable;
+use resources::Resources;
+use shader::ShaderSource;
+use terrain::CLEAR_COLOR;
+
+const DEFAULT_SCALE: f32 = 2.;
+
+/// A crosshair at the center of the window, drawn as a single
+/// textured quad with an orthographic projection in pixels.
+pub struct Hud {
+    program: Program<(), (), HudUniforms>,
+    quad: Tess<()>,
+    tex: Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>,
+    projection: M44,
+    scale: f32,
+}
+
+impl Hud {
+    /// Create a HUD for a window of this size, using the crosshair
+    /// from `resources` and the shader pair from `shaders`.
+    /// # Panics
+    /// Panics if the shaders fail to load.
+    pub fn new(resources: &Resources, shaders: ShaderSource, window_size: (u32, u32)) -> Hud {
+        let (vs, fs) = shaders.text().unwrap();
+        let (program, warnings) = Program::from_strings(None, &vs, None, &fs).unwrap();
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        Hud {
+            program,
+            quad: Tess::attributeless(Mode::TriangleStrip, 4),
+            tex: resources.crosshair_tex(),
+            projection: Self::projection(window_size),
+            scale: DEFAULT_SCALE,
+        }
+    }
+    
+    /// Keep the crosshair centered, and its size in pixels
+    /// the same, after the window is resized.
+    pub fn set_window_size(&mut self, window_size: (u32, u32)) {
+        self.projection = Self::projection(window_size);
+    }
+    
+    /// Set how many pixels on screen each texel
+    /// of the crosshair image covers.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.);
+    }
+    
+    /// Get how many pixels on screen each texel
+    /// of the crosshair image covers.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+    
+    // An orthographic projection in pixels, with the
+    // origin at the center of the window.
+    fn projection(window_size: (u32, u32)) -> M44 {
+        let (w, h) = (window_size.0 as f32 / 2., window_size.1 as f32 / 2.);
+        
+        Ortho::new(-w, w, -h, h, -1., 1.).to_matrix()
+    }
+}
+
+impl Drawable for Hud {
+    fn draw_in(&self, gpu: &Gpu, shade_gate: &ShadingGate, _camera: &Camera) {
+        gpu.bind_texture(&self.tex.0);
+        
+        let size = [self.tex.1.width as f32 * self.scale,
+                    self.tex.1.height as f32 * self.scale];
+        
+        shade_gate.shade(&self.program, |render_gate, uniforms| {
+            uniforms.projection.update(self.projection);
+            uniforms.size.update(size);
+            
+            // Always drawn over the scene.
+            let render_state = RenderState::default()
+                               .set_depth_test(DepthTest::Disabled);
+            render_gate.render(render_state, |tess_gate| {
+                tess_gate.render((&self.quad).into());
+            });
+        });
+    }
+    
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        entry(|gpu| {
+            pipeline(render_target, CLEAR_COLOR, |shade_gate| {
+                self.draw_in(&gpu, &shade_gate, camera);
+            });
+        });
+    }
+}
+
+/// The HUD's uniform interface.
+struct HudUniforms {
+    // Maps pixels, centered on the window, to clip space.
+    projection: Uniform<M44>,
+    
+    // Size of the quad, in pixels.
+    size: Uniform<[f32; 2]>,
+}
+
+impl UniformInterface for HudUniforms {
+    fn uniform_interface(builder: UniformBuilder)
+            -> Result<(HudUniforms, Vec<UniformWarning>), ProgramError> {
+        
+        let projection = builder.ask("projection").unwrap();
+        let size = builder.ask("size").unwrap();
+        
+        Ok((HudUniforms {
+            projection,
+            size,
+        }, Vec::new()))
+    }
+}
@@ -9,13 +9,17 @@ extern crate luminance_glfw;
 extern crate noise;
 extern crate png;
 
-pub use viewer::Viewer;
+pub use viewer::{TerrainKind, Viewer, ViewerOptions};
 
+pub mod blit;
 pub mod camera;
+pub mod hud;
 #[macro_use]
 pub mod maths;
 pub mod model;
 pub mod resources;
 pub mod shader;
+pub mod sky;
+pub mod skybox;
 pub mod terrain;
 pub mod viewer;
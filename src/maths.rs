@@ -1,6 +1,9 @@
 //! General 3D game math.
 
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
 use luminance::linear::M44;
+use camera::Camera;
 
 /// Creates a luminance 4x4 matrix.
 /// When specifying a matrix normally, the rows and columns
@@ -77,6 +80,14 @@ impl Translation {
         self.y += y;
         self.z += z;
     }
+    
+    /// Interpolate toward `other`, where `t = 0` is this
+    /// translation and `t = 1` is `other`.
+    pub fn lerp(&self, other: &Translation, t: f32) -> Translation {
+        Translation::new(lerp(self.x, other.x, t),
+                         lerp(self.y, other.y, t),
+                         lerp(self.z, other.z, t))
+    }
 }
 
 impl ToMatrix for Translation {
@@ -90,6 +101,101 @@ impl ToMatrix for Translation {
     }
 }
 
+/// A 3D vector, for direction and distance math.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    /// Create a new Vec3 with these values.
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 {
+            x,
+            y,
+            z,
+        }
+    }
+    
+    /// Calculate the dot product.
+    pub fn dot(&self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    
+    /// Calculate the cross product, which is perpendicular to both.
+    pub fn cross(&self, other: Vec3) -> Vec3 {
+        Vec3::new(self.y * other.z - self.z * other.y,
+                  self.z * other.x - self.x * other.z,
+                  self.x * other.y - self.y * other.x)
+    }
+    
+    /// Calculate the squared length, avoiding a square root.
+    pub fn length_squared(&self) -> f32 {
+        self.dot(*self)
+    }
+    
+    /// Calculate the length.
+    pub fn length(&self) -> f32 {
+        self.length_squared().sqrt()
+    }
+    
+    /// Get the vector of length one in the same direction.
+    /// The zero vector is returned unchanged.
+    pub fn normalize(&self) -> Vec3 {
+        let l = self.length();
+        
+        if l == 0. {
+            *self
+        } else {
+            *self * (1. / l)
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    
+    fn mul(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl From<Translation> for Vec3 {
+    fn from(t: Translation) -> Vec3 {
+        Vec3::new(t.x, t.y, t.z)
+    }
+}
+
+impl<'a> From<&'a Translation> for Vec3 {
+    fn from(t: &'a Translation) -> Vec3 {
+        Vec3::new(t.x, t.y, t.z)
+    }
+}
+
+impl From<Vec3> for Translation {
+    fn from(v: Vec3) -> Translation {
+        Translation::new(v.x, v.y, v.z)
+    }
+}
+
 /// Stores a rotation. Only rotations about the X and Y axis
 /// are preformed.
 #[derive(Clone, Debug)]
@@ -112,6 +218,27 @@ impl Rotation {
         self.x += x;
         self.y += y;
     }
+    
+    /// Interpolate toward `other`, where `t = 0` is this rotation
+    /// and `t = 1` is `other`. Each angle turns the short way
+    /// around, so the result may differ from `other` by whole turns.
+    pub fn lerp(&self, other: &Rotation, t: f32) -> Rotation {
+        Rotation::new(lerp_angle(self.x, other.x, t),
+                      lerp_angle(self.y, other.y, t))
+    }
+}
+
+// Interpolate between angles in radians, the short way around.
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let turn = 2. * PI;
+    let mut diff = (b - a) % turn;
+    if diff > PI {
+        diff -= turn;
+    } else if diff < -PI {
+        diff += turn;
+    }
+    
+    a + diff * t
 }
 
 impl ToMatrix for Rotation {
@@ -138,6 +265,116 @@ impl ToMatrix for Rotation {
     }
 }
 
+/// A rotation stored as a unit quaternion. Unlike `Rotation`, it can
+/// turn about any axis, and composes and interpolates smoothly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    /// Create a new Quat with these values. It should be
+    /// of length one to be a rotation, see `normalize`.
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quat {
+        Quat {
+            x,
+            y,
+            z,
+            w,
+        }
+    }
+    
+    /// Get the rotation that does not turn at all.
+    pub fn identity() -> Quat {
+        Quat::new(0., 0., 0., 1.)
+    }
+    
+    /// Create a rotation of `angle` radians about `axis`, counterclockwise
+    /// looking down the axis toward the origin. The axis is normalized.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.).sin_cos();
+        
+        Quat::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+    
+    /// Calculate the dot product, the cosine of half the
+    /// angle between two unit quaternions.
+    pub fn dot(&self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+    
+    /// Get the quaternion of length one in the same direction, undoing
+    /// the drift from many compositions. The zero quaternion is
+    /// returned unchanged.
+    pub fn normalize(&self) -> Quat {
+        let l = self.dot(*self).sqrt();
+        
+        if l == 0. {
+            *self
+        } else {
+            Quat::new(self.x / l, self.y / l, self.z / l, self.w / l)
+        }
+    }
+    
+    /// Spherically interpolate toward `other`, where `t = 0` is this
+    /// rotation and `t = 1` is `other`, turning at a constant rate
+    /// the short way around.
+    pub fn slerp(&self, other: Quat, t: f32) -> Quat {
+        // `q` and `-q` are the same rotation, so pick the nearer one.
+        let mut cos = self.dot(other);
+        let other = if cos < 0. {
+            cos = -cos;
+            Quat::new(-other.x, -other.y, -other.z, -other.w)
+        } else {
+            other
+        };
+        
+        // Nearly the same rotation, where the sine below vanishes.
+        let (a, b) = if cos > 0.9995 {
+            (1. - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            
+            (((1. - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        
+        Quat::new(self.x * a + other.x * b,
+                  self.y * a + other.y * b,
+                  self.z * a + other.z * b,
+                  self.w * a + other.w * b).normalize()
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+    
+    /// Compose two rotations. The result applies `other` first.
+    fn mul(self, other: Quat) -> Quat {
+        Quat::new(self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+                  self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+                  self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+                  self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z)
+    }
+}
+
+impl ToMatrix for Quat {
+    fn to_matrix(&self) -> M44 {
+        let Quat { x, y, z, w } = *self;
+        
+        mat4! [
+            1. - 2. * (y * y + z * z),  2. * (x * y - z * w),       2. * (x * z + y * w),       0.,
+            2. * (x * y + z * w),       1. - 2. * (x * x + z * z),  2. * (y * z - x * w),       0.,
+            2. * (x * z - y * w),       2. * (y * z + x * w),       1. - 2. * (x * x + y * y),  0.,
+            0.,                         0.,                         0.,                         1.,
+        ]
+    }
+}
+
 /// Stores a 3D projection.
 #[derive(Clone, Debug)]
 pub struct Projection {
@@ -150,7 +387,7 @@ pub struct Projection {
 impl Projection {
     /// Create a new Projection with these values.
     /// # Parameters
-    /// * `fov`: Field of view **in radians**
+    /// * `fov`: Vertical field of view **in radians**
     /// * `aspect`: Aspect ratio
     /// * `near` and `far`: Clipping planes
     pub fn new(fov: f32, aspect: f32, near: f32, far: f32) -> Projection {
@@ -161,6 +398,20 @@ impl Projection {
             far
         }
     }
+    
+    /// Create a Projection from a vertical field of view **in degrees**.
+    pub fn vertical_fov(degrees: f32, aspect: f32, near: f32, far: f32) -> Projection {
+        Self::new(degrees.to_radians(), aspect, near, far)
+    }
+    
+    /// Create a Projection from a horizontal field of view **in degrees**.
+    /// The vertical field of view is derived from the aspect ratio, so
+    /// the horizontal extent stays the same on any window shape.
+    pub fn horizontal_fov(degrees: f32, aspect: f32, near: f32, far: f32) -> Projection {
+        let fov = 2. * ((degrees.to_radians() / 2.).tan() / aspect).atan();
+        
+        Self::new(fov, aspect, near, far)
+    }
 }
 
 impl ToMatrix for Projection {
@@ -181,7 +432,54 @@ impl ToMatrix for Projection {
     }
 }
 
+/// Stores an orthographic projection.
+#[derive(Clone, Debug)]
+pub struct Ortho {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Ortho {
+    /// Create a new Ortho with these values.
+    /// # Parameters
+    /// * `left`, `right`, `bottom`, and `top`: Extents of the view box
+    /// * `near` and `far`: Clipping planes
+    pub fn new(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Ortho {
+        Ortho {
+            left,
+            right,
+            bottom,
+            top,
+            near,
+            far,
+        }
+    }
+}
+
+impl ToMatrix for Ortho {
+    fn to_matrix(&self) -> M44 {
+        let width = self.right - self.left;
+        let height = self.top - self.bottom;
+        let depth = self.far - self.near;
+        
+        mat4! [
+            2. / width,     0.,             0.,             -(self.right + self.left) / width,
+            0.,             2. / height,    0.,             -(self.top + self.bottom) / height,
+            0.,             0.,             -2. / depth,    -(self.far + self.near) / depth,
+            0.,             0.,             0.,             1.,
+        ]
+    }
+}
+
 /// Multiplies two 4x4 matrices, returning the product.
+///
+/// This is the usual product `left * right` of the matrices as
+/// written with `mat4!`, so applying the result to a point applies
+/// `right` first. Matrices are stored by column, `m[column][row]`.
 pub fn matrix_mul(left: &M44, right: &M44) -> M44 {
     let mut result = mat4! [
         0., 0., 0., 0.,
@@ -201,6 +499,198 @@ pub fn matrix_mul(left: &M44, right: &M44) -> M44 {
     result
 }
 
+/// Inverts a 4x4 matrix, returning `None` if it is singular.
+pub fn matrix_inverse(m: &M44) -> Option<M44> {
+    // Cofactor expansion, as in MESA's gluInvertMatrix. The
+    // inverse of the transpose is the transpose of the inverse,
+    // so the column-major layout needs no special handling.
+    let a = |i: usize| m[i / 4][i % 4];
+    let mut inv = [0.; 16];
+    
+    inv[0] = a(5) * a(10) * a(15) - a(5) * a(11) * a(14) - a(9) * a(6) * a(15)
+           + a(9) * a(7) * a(14) + a(13) * a(6) * a(11) - a(13) * a(7) * a(10);
+    inv[4] = -a(4) * a(10) * a(15) + a(4) * a(11) * a(14) + a(8) * a(6) * a(15)
+           - a(8) * a(7) * a(14) - a(12) * a(6) * a(11) + a(12) * a(7) * a(10);
+    inv[8] = a(4) * a(9) * a(15) - a(4) * a(11) * a(13) - a(8) * a(5) * a(15)
+           + a(8) * a(7) * a(13) + a(12) * a(5) * a(11) - a(12) * a(7) * a(9);
+    inv[12] = -a(4) * a(9) * a(14) + a(4) * a(10) * a(13) + a(8) * a(5) * a(14)
+            - a(8) * a(6) * a(13) - a(12) * a(5) * a(10) + a(12) * a(6) * a(9);
+    inv[1] = -a(1) * a(10) * a(15) + a(1) * a(11) * a(14) + a(9) * a(2) * a(15)
+           - a(9) * a(3) * a(14) - a(13) * a(2) * a(11) + a(13) * a(3) * a(10);
+    inv[5] = a(0) * a(10) * a(15) - a(0) * a(11) * a(14) - a(8) * a(2) * a(15)
+           + a(8) * a(3) * a(14) + a(12) * a(2) * a(11) - a(12) * a(3) * a(10);
+    inv[9] = -a(0) * a(9) * a(15) + a(0) * a(11) * a(13) + a(8) * a(1) * a(15)
+           - a(8) * a(3) * a(13) - a(12) * a(1) * a(11) + a(12) * a(3) * a(9);
+    inv[13] = a(0) * a(9) * a(14) - a(0) * a(10) * a(13) - a(8) * a(1) * a(14)
+            + a(8) * a(2) * a(13) + a(12) * a(1) * a(10) - a(12) * a(2) * a(9);
+    inv[2] = a(1) * a(6) * a(15) - a(1) * a(7) * a(14) - a(5) * a(2) * a(15)
+           + a(5) * a(3) * a(14) + a(13) * a(2) * a(7) - a(13) * a(3) * a(6);
+    inv[6] = -a(0) * a(6) * a(15) + a(0) * a(7) * a(14) + a(4) * a(2) * a(15)
+           - a(4) * a(3) * a(14) - a(12) * a(2) * a(7) + a(12) * a(3) * a(6);
+    inv[10] = a(0) * a(5) * a(15) - a(0) * a(7) * a(13) - a(4) * a(1) * a(15)
+            + a(4) * a(3) * a(13) + a(12) * a(1) * a(7) - a(12) * a(3) * a(5);
+    inv[14] = -a(0) * a(5) * a(14) + a(0) * a(6) * a(13) + a(4) * a(1) * a(14)
+            - a(4) * a(2) * a(13) - a(12) * a(1) * a(6) + a(12) * a(2) * a(5);
+    inv[3] = -a(1) * a(6) * a(11) + a(1) * a(7) * a(10) + a(5) * a(2) * a(11)
+           - a(5) * a(3) * a(10) - a(9) * a(2) * a(7) + a(9) * a(3) * a(6);
+    inv[7] = a(0) * a(6) * a(11) - a(0) * a(7) * a(10) - a(4) * a(2) * a(11)
+           + a(4) * a(3) * a(10) + a(8) * a(2) * a(7) - a(8) * a(3) * a(6);
+    inv[11] = -a(0) * a(5) * a(11) + a(0) * a(7) * a(9) + a(4) * a(1) * a(11)
+            - a(4) * a(3) * a(9) - a(8) * a(1) * a(7) + a(8) * a(3) * a(5);
+    inv[15] = a(0) * a(5) * a(10) - a(0) * a(6) * a(9) - a(4) * a(1) * a(10)
+            + a(4) * a(2) * a(9) + a(8) * a(1) * a(6) - a(8) * a(2) * a(5);
+    
+    let det = a(0) * inv[0] + a(1) * inv[4] + a(2) * inv[8] + a(3) * inv[12];
+    if det.abs() < ::std::f32::EPSILON {
+        return None;
+    }
+    
+    let mut result = IDENTITY;
+    for (i, v) in inv.iter().enumerate() {
+        result[i / 4][i % 4] = v / det;
+    }
+    
+    Some(result)
+}
+
+/// Remove the translation from a view matrix, keeping only its
+/// rotation, so that what is drawn with it stays infinitely far
+/// away however the camera moves.
+pub fn without_translation(m: &M44) -> M44 {
+    let mut result = *m;
+    result[3] = [0., 0., 0., 1.];
+    
+    result
+}
+
+/// Transposes a 4x4 matrix, swapping its rows and columns.
+pub fn matrix_transpose(m: &M44) -> M44 {
+    let mut result = IDENTITY;
+    for col in 0..4 {
+        for row in 0..4 {
+            result[col][row] = m[row][col];
+        }
+    }
+    
+    result
+}
+
+/// Linearly interpolate from `a` to `b`. `t` is not
+/// clamped, so values outside `[0, 1]` extrapolate.
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Build a view matrix for an eye at `eye` looking toward `target`,
+/// with `up` roughly upward on screen. Like the camera's matrix, the
+/// view looks along -Z. `up` must not be parallel to the view.
+pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> M44 {
+    let f = (target - eye).normalize();
+    let s = f.cross(up).normalize();
+    let u = s.cross(f);
+    
+    mat4! [
+        s.x,    s.y,    s.z,    -s.dot(eye),
+        u.x,    u.y,    u.z,    -u.dot(eye),
+        -f.x,   -f.y,   -f.z,   f.dot(eye),
+        0.,     0.,     0.,     1.,
+    ]
+}
+
+/// Transform a point by a 4x4 matrix, including
+/// the perspective divide.
+pub fn transform_point(m: &M44, p: (f32, f32, f32)) -> (f32, f32, f32) {
+    let mut out = [0.; 4];
+    for (row, o) in out.iter_mut().enumerate() {
+        *o = m[0][row] * p.0 + m[1][row] * p.1 + m[2][row] * p.2 + m[3][row];
+    }
+    
+    (out[0] / out[3], out[1] / out[3], out[2] / out[3])
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Debug)]
+pub struct Aabb {
+    pub min: Translation,
+    pub max: Translation,
+}
+
+impl Aabb {
+    /// Create a bounding box from its minimum and maximum corners.
+    pub fn new(min: Translation, max: Translation) -> Aabb {
+        Aabb {
+            min,
+            max,
+        }
+    }
+    
+    /// Move the translation to the nearest point inside the box.
+    pub fn clamp(&self, t: &mut Translation) {
+        t.x = t.x.max(self.min.x).min(self.max.x);
+        t.y = t.y.max(self.min.y).min(self.max.y);
+        t.z = t.z.max(self.min.z).min(self.max.z);
+    }
+}
+
+/// A half-line, starting at `origin` and going along `dir`.
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub dir: Vec3,
+}
+
+impl Ray {
+    /// Create a ray from its origin and direction. Distances along
+    /// the ray are in multiples of the length of `dir`.
+    pub fn new(origin: Vec3, dir: Vec3) -> Ray {
+        Ray {
+            origin,
+            dir,
+        }
+    }
+    
+    /// Get the point at distance `t` along the ray.
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.dir * t
+    }
+    
+    /// Find the distance along the ray to where it enters the box,
+    /// using the slab method. A ray starting inside the box hits
+    /// it at `0`. Returns `None` if the ray misses the box.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let origin = [self.origin.x, self.origin.y, self.origin.z];
+        let dir = [self.dir.x, self.dir.y, self.dir.z];
+        let min = [aabb.min.x, aabb.min.y, aabb.min.z];
+        let max = [aabb.max.x, aabb.max.y, aabb.max.z];
+        
+        let mut near = 0f32;
+        let mut far = f32::INFINITY;
+        for i in 0..3 {
+            if dir[i] == 0. {
+                // Parallel to this slab, so never entering
+                // or leaving it.
+                if origin[i] < min[i] || origin[i] > max[i] {
+                    return None;
+                }
+                
+                continue;
+            }
+            
+            let t1 = (min[i] - origin[i]) / dir[i];
+            let t2 = (max[i] - origin[i]) / dir[i];
+            
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+            
+            if near > far {
+                return None;
+            }
+        }
+        
+        Some(near)
+    }
+}
+
 /// A 3D plane defined as (A, B, C, D).
 #[derive(Clone, Debug)]
 pub struct Plane {
@@ -221,19 +711,57 @@ impl Plane {
         }
     }
     
+    /// Get the normal of the plane, (A, B, C).
+    pub fn normal(&self) -> Vec3 {
+        Vec3::new(self.a, self.b, self.c)
+    }
+    
+    /// Calculate the signed distance from a normalized plane to
+    /// a point. It is positive on the side the normal points to.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        self.normal().dot(p) + self.d
+    }
+    
     /// Normalize the plane.
     pub fn normalize(&mut self) {
-        let l = (sq(self.a) + sq(self.b) + sq(self.c)).sqrt();
+        let l = self.normal().length();
         
         self.a /= l;
         self.b /= l;
         self.c /= l;
         self.d /= l;
     }
+    
+    /// Find the point where three planes meet, or `None` if
+    /// two of them are parallel and there is no single point.
+    pub fn intersection(a: &Plane, b: &Plane, c: &Plane) -> Option<Vec3> {
+        let (na, nb, nc) = (a.normal(), b.normal(), c.normal());
+        let bc = nb.cross(nc);
+        let denom = na.dot(bc);
+        
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        
+        let p = bc * -a.d + nc.cross(na) * -b.d + na.cross(nb) * -c.d;
+        
+        Some(p * (1. / denom))
+    }
 }
 
 type FlatM44 = [f32; 16];
 
+// Lay a matrix out as one array, column after column,
+// so `m[column][row]` is at `column * 4 + row`.
+fn flatten(m: &M44) -> FlatM44 {
+    let mut flat = [0.; 16];
+    for (i, v) in flat.iter_mut().enumerate() {
+        *v = m[i / 4][i % 4];
+    }
+    
+    flat
+}
+
 /// Represents the frustum of the camera.
 #[derive(Clone, Debug)]
 pub struct Frustum {
@@ -241,14 +769,17 @@ pub struct Frustum {
 }
 
 impl Frustum {
+    /// Create the frustum seen by a camera.
+    pub fn from_camera(camera: &Camera) -> Frustum {
+        Self::new(camera.projection_matrix(), &camera.to_matrix())
+    }
+    
     /// Create the frustum from the projection and view matrices.
     pub fn new(proj: &M44, view: &M44) -> Frustum {
         // http://www.crownandcutlass.com/features/technicaldetails/frustum.html
         // http://www.lighthouse3d.com/tutorials/view-frustum-culling/clip-space-approach-extracting-the-planes/
         
-        let mat: FlatM44 = unsafe {
-            ::std::mem::transmute(matrix_mul(proj, view))
-        };
+        let mat = flatten(&matrix_mul(proj, view));
         
         let mut right  = Plane::new(mat[3]  - mat[0],
                                     mat[7]  - mat[4],
@@ -309,9 +840,405 @@ impl Frustum {
     pub fn planes(&self) -> &[Plane; 6] {
         &self.planes
     }
+    
+    /// Calculate the eight corners of the frustum in world space,
+    /// where the side planes meet the near and far planes. Bit `1`
+    /// of the index picks the right side over the left, bit `2` the
+    /// top over the bottom, and bit `4` the far plane over the near.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let p = &self.planes;
+        
+        let mut corners = [Vec3::new(0., 0., 0.); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 != 0 { &p[0] } else { &p[1] };
+            let y = if i & 2 != 0 { &p[3] } else { &p[2] };
+            let z = if i & 4 != 0 { &p[4] } else { &p[5] };
+            
+            // The planes of a valid projection are never parallel.
+            *corner = Plane::intersection(x, y, z).unwrap();
+        }
+        
+        corners
+    }
+    
+    /// Determine if an axis-aligned box, given by its minimum and
+    /// maximum corners, may be inside the frustum. For each plane,
+    /// only the corner furthest along the plane's normal is tested,
+    /// and the box is outside if that corner is behind the plane.
+    ///
+    /// This is conservative: a few boxes near the frustum's edges
+    /// pass even though they are just outside.
+    pub fn intersects_aabb(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        for p in &self.planes {
+            let positive = Vec3::new(if p.a >= 0. { max[0] } else { min[0] },
+                                     if p.b >= 0. { max[1] } else { min[1] },
+                                     if p.c >= 0. { max[2] } else { min[2] });
+            
+            if p.distance(positive) < 0. {
+                return false;
+            }
+        }
+        
+        true
+    }
 }
 
-// Utility
-fn sq(x: f32) -> f32 {
-    x * x
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn cross_is_orthogonal() {
+        let a = Vec3::new(1., 2., 3.);
+        let b = Vec3::new(-4., 0.5, 2.);
+        let c = a.cross(b);
+        
+        assert!(c.dot(a).abs() < 1e-5);
+        assert!(c.dot(b).abs() < 1e-5);
+        assert_eq!(Vec3::new(1., 0., 0.).cross(Vec3::new(0., 1., 0.)), Vec3::new(0., 0., 1.));
+    }
+    
+    #[test]
+    fn dot_of_known_vectors() {
+        assert_eq!(Vec3::new(1., 2., 3.).dot(Vec3::new(4., -5., 6.)), 12.);
+        assert_eq!(Vec3::new(1., 0., 0.).dot(Vec3::new(0., 1., 0.)), 0.);
+    }
+    
+    #[test]
+    fn normalize_gives_unit_length() {
+        let n = Vec3::new(3., -4., 12.).normalize();
+        
+        assert!((n.length() - 1.).abs() < 1e-6);
+        assert!((n.x - 3. / 13.).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn normalize_keeps_zero_vector() {
+        let zero = Vec3::new(0., 0., 0.);
+        
+        assert_eq!(zero.normalize(), zero);
+    }
+    
+    // Two non-symmetric matrices, and their product `A * B`.
+    const A: M44 = mat4! [
+        1., 2., 0., 0.,
+        0., 1., 3., 0.,
+        0., 0., 1., 4.,
+        5., 0., 0., 1.,
+    ];
+    
+    const B: M44 = mat4! [
+        2., 0., 0., 1.,
+        1., 1., 0., 0.,
+        0., 3., 1., 0.,
+        0., 0., 2., 1.,
+    ];
+    
+    const A_B: M44 = mat4! [
+        4., 2., 0., 1.,
+        1., 10., 3., 0.,
+        0., 3., 9., 4.,
+        10., 0., 2., 6.,
+    ];
+    
+    #[test]
+    fn matrix_mul_of_known_matrices() {
+        assert_eq!(matrix_mul(&A, &B), A_B);
+        assert!(matrix_mul(&B, &A) != A_B);
+    }
+    
+    #[test]
+    fn matrix_mul_by_identity() {
+        assert_eq!(matrix_mul(&IDENTITY, &A), A);
+        assert_eq!(matrix_mul(&A, &IDENTITY), A);
+    }
+    
+    // The box from -10 to 10 on X and Y, and -1 to -100 on Z.
+    fn box_frustum() -> Frustum {
+        Frustum::new(&Ortho::new(-10., 10., -10., 10., 1., 100.).to_matrix(), &IDENTITY)
+    }
+    
+    #[test]
+    fn aabb_inside_frustum() {
+        assert!(box_frustum().intersects_aabb([-1., -1., -10.], [1., 1., -5.]));
+    }
+    
+    #[test]
+    fn aabb_outside_frustum() {
+        let frustum = box_frustum();
+        
+        assert!(!frustum.intersects_aabb([20., -1., -10.], [30., 1., -5.]));
+        assert!(!frustum.intersects_aabb([-1., -30., -10.], [1., -20., -5.]));
+        assert!(!frustum.intersects_aabb([-1., -1., 5.], [1., 1., 6.]));
+        assert!(!frustum.intersects_aabb([-1., -1., -200.], [1., 1., -150.]));
+    }
+    
+    #[test]
+    fn aabb_straddling_plane() {
+        let frustum = box_frustum();
+        
+        assert!(frustum.intersects_aabb([5., -1., -10.], [15., 1., -5.]));
+        assert!(frustum.intersects_aabb([-1., -1., -2.], [1., 1., 3.]));
+    }
+    
+    fn unit_box() -> Aabb {
+        Aabb::new(Translation::new(0., 0., 0.), Translation::new(1., 1., 1.))
+    }
+    
+    #[test]
+    fn ray_hits_aabb() {
+        let ray = Ray::new(Vec3::new(-2., 0.5, 0.5), Vec3::new(1., 0., 0.));
+        
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(2.));
+    }
+    
+    #[test]
+    fn ray_misses_aabb() {
+        let beside = Ray::new(Vec3::new(-2., 1.5, 0.5), Vec3::new(1., 0., 0.));
+        let away = Ray::new(Vec3::new(-2., 0.5, 0.5), Vec3::new(-1., 0., 0.));
+        
+        assert_eq!(beside.intersect_aabb(&unit_box()), None);
+        assert_eq!(away.intersect_aabb(&unit_box()), None);
+    }
+    
+    #[test]
+    fn ray_inside_aabb_hits_at_zero() {
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.3, -1., 0.2));
+        
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.));
+    }
+    
+    #[test]
+    fn lerp_scalars() {
+        assert_eq!(lerp(2., 6., 0.), 2.);
+        assert_eq!(lerp(2., 6., 0.25), 3.);
+        assert_eq!(lerp(2., 6., 1.), 6.);
+        assert_eq!(lerp(2., 6., 1.5), 8.);
+    }
+    
+    #[test]
+    fn translation_lerp_midpoint() {
+        let a = Translation::new(0., 10., -4.);
+        let b = Translation::new(2., 20., 4.);
+        let mid = a.lerp(&b, 0.5);
+        
+        assert_eq!((mid.x, mid.y, mid.z), (1., 15., 0.));
+    }
+    
+    #[test]
+    fn rotation_lerp_turns_short_way() {
+        let a = Rotation::new(0.1, 3.);
+        let b = Rotation::new(-0.1, -3.);
+        let mid = a.lerp(&b, 0.5);
+        
+        // Across the half turn, not back through zero.
+        assert!(mid.x.abs() < 1e-6);
+        assert!((mid.y - PI).abs() < 1e-5);
+        
+        let end = a.lerp(&b, 1.);
+        assert!((end.y - (2. * PI - 3.)).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn look_at_puts_target_ahead() {
+        let eye = Vec3::new(1., 2., 3.);
+        let target = Vec3::new(4., 6., 3.);
+        let view = look_at(eye, target, Vec3::new(0., 1., 0.));
+        
+        let (x, y, z) = transform_point(&view, (target.x, target.y, target.z));
+        assert!(x.abs() < 1e-5 && y.abs() < 1e-5);
+        assert!((z + 5.).abs() < 1e-5);
+        
+        let (x, y, z) = transform_point(&view, (eye.x, eye.y, eye.z));
+        assert!(x.abs() < 1e-5 && y.abs() < 1e-5 && z.abs() < 1e-5);
+    }
+    
+    fn assert_quat_near(a: Quat, b: Quat) {
+        assert!((a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5 &&
+                (a.z - b.z).abs() < 1e-5 && (a.w - b.w).abs() < 1e-5,
+                "{:?} != {:?}", a, b);
+    }
+    
+    fn assert_matrix_near(a: &M44, b: &M44) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a[i][j] - b[i][j]).abs() < 1e-5, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+    
+    #[test]
+    fn quat_identity_does_not_turn() {
+        assert_eq!(Quat::identity().to_matrix(), IDENTITY);
+        
+        let q = Quat::from_axis_angle(Vec3::new(1., 2., 3.), 0.7);
+        assert_quat_near(q * Quat::identity(), q);
+        assert_quat_near(Quat::identity() * q, q);
+    }
+    
+    #[test]
+    fn quat_axis_angle_matches_rotation() {
+        let q = Quat::from_axis_angle(Vec3::new(2., 0., 0.), 0.7);
+        assert_matrix_near(&q.to_matrix(), &Rotation::new(0.7, 0.).to_matrix());
+        
+        let q = Quat::from_axis_angle(Vec3::new(0., 0., 1.), PI / 2.);
+        let (x, y, z) = transform_point(&q.to_matrix(), (1., 0., 0.));
+        assert!(x.abs() < 1e-5 && (y - 1.).abs() < 1e-5 && z.abs() < 1e-5);
+    }
+    
+    #[test]
+    fn quat_mul_composes_matrices() {
+        let a = Quat::from_axis_angle(Vec3::new(0., 1., 0.), 1.2);
+        let b = Quat::from_axis_angle(Vec3::new(1., 0., 1.), -0.4);
+        
+        assert_matrix_near(&(a * b).to_matrix(), &matrix_mul(&a.to_matrix(), &b.to_matrix()));
+    }
+    
+    #[test]
+    fn quat_slerp_endpoints_and_midpoint() {
+        let axis = Vec3::new(0., 1., 0.);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(axis, 2.);
+        
+        assert_quat_near(a.slerp(b, 0.), a);
+        assert_quat_near(a.slerp(b, 1.), b);
+        assert_quat_near(a.slerp(b, 0.5), Quat::from_axis_angle(axis, 1.));
+    }
+    
+    #[test]
+    fn quat_normalize() {
+        assert_quat_near(Quat::new(0., 0., 3., 4.).normalize(), Quat::new(0., 0., 0.6, 0.8));
+        assert_eq!(Quat::new(0., 0., 0., 0.).normalize(), Quat::new(0., 0., 0., 0.));
+    }
+    
+    fn assert_vec_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+    
+    #[test]
+    fn three_axis_planes_meet_at_point() {
+        let p = Plane::intersection(&Plane::new(1., 0., 0., -2.),
+                                    &Plane::new(0., 1., 0., 3.),
+                                    &Plane::new(0., 0., 2., -8.));
+        
+        assert_vec_near(p.unwrap(), Vec3::new(2., -3., 4.));
+    }
+    
+    #[test]
+    fn parallel_planes_do_not_meet() {
+        assert!(Plane::intersection(&Plane::new(1., 0., 0., 0.),
+                                    &Plane::new(-2., 0., 0., 5.),
+                                    &Plane::new(0., 1., 0., 0.)).is_none());
+    }
+    
+    #[test]
+    fn box_frustum_corners() {
+        let corners = box_frustum().corners();
+        
+        for (i, &corner) in corners.iter().enumerate() {
+            let x = if i & 1 != 0 { 10. } else { -10. };
+            let y = if i & 2 != 0 { 10. } else { -10. };
+            let z = if i & 4 != 0 { -100. } else { -1. };
+            
+            assert_vec_near(corner, Vec3::new(x, y, z));
+        }
+    }
+    
+    #[test]
+    fn perspective_frustum_corners() {
+        let proj = Projection::new(PI / 2., 2., 1., 10.).to_matrix();
+        let corners = Frustum::new(&proj, &IDENTITY).corners();
+        
+        assert_vec_near(corners[0], Vec3::new(-2., -1., -1.));
+        assert_vec_near(corners[3], Vec3::new(2., 1., -1.));
+        assert_vec_near(corners[4], Vec3::new(-20., -10., -10.));
+        assert_vec_near(corners[7], Vec3::new(20., 10., -10.));
+    }
+    
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let t = matrix_transpose(&A);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(t[i][j], A[j][i]);
+            }
+        }
+        
+        assert_eq!(matrix_transpose(&t), A);
+    }
+    
+    #[test]
+    fn inverse_undoes_translation_and_rotation() {
+        let m = matrix_mul(&Rotation::new(0.3, -1.1).to_matrix(),
+                           &Translation::new(4., -2., 7.).to_matrix());
+        let inv = matrix_inverse(&m).unwrap();
+        
+        assert_matrix_near(&matrix_mul(&m, &inv), &IDENTITY);
+        assert_matrix_near(&matrix_mul(&inv, &m), &IDENTITY);
+        assert_matrix_near(&matrix_inverse(&Translation::new(4., -2., 7.).to_matrix()).unwrap(),
+                           &Translation::new(-4., 2., -7.).to_matrix());
+    }
+    
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let mut m = IDENTITY;
+        m[2] = [0., 0., 0., 0.];
+        
+        assert!(matrix_inverse(&m).is_none());
+    }
+    
+    fn camera_matrices() -> (M44, M44) {
+        let proj = Projection::vertical_fov(70., 4. / 3., 0.1, 500.).to_matrix();
+        let view = matrix_mul(&Rotation::new(0.4, 2.3).to_matrix(),
+                              &Translation::new(-12.5, -40., 3.25).to_matrix());
+        
+        (proj, view)
+    }
+    
+    #[test]
+    fn flatten_matches_memory_layout() {
+        let (proj, view) = camera_matrices();
+        
+        for m in &[A, B, proj, matrix_mul(&proj, &view)] {
+            let old: FlatM44 = unsafe { ::std::mem::transmute(*m) };
+            let new = flatten(m);
+            
+            for (o, n) in old.iter().zip(new.iter()) {
+                assert_eq!(o.to_bits(), n.to_bits());
+            }
+        }
+    }
+    
+    #[test]
+    fn frustum_planes_match_transmuted_extraction() {
+        let (proj, view) = camera_matrices();
+        let mat: FlatM44 = unsafe { ::std::mem::transmute(matrix_mul(&proj, &view)) };
+        
+        // Right, left, bottom, top, far and near: the fourth row
+        // minus or plus the first, second or third.
+        let rows = [(0, -1.), (0, 1.), (1, 1.), (1, -1.), (2, -1.), (2, 1.)];
+        
+        let frustum = Frustum::new(&proj, &view);
+        for (p, &(row, sign)) in frustum.planes().iter().zip(rows.iter()) {
+            let mut expected = Plane::new(mat[3]  + sign * mat[row],
+                                          mat[7]  + sign * mat[row + 4],
+                                          mat[11] + sign * mat[row + 8],
+                                          mat[15] + sign * mat[row + 12]);
+            expected.normalize();
+            
+            assert_eq!(p.a.to_bits(), expected.a.to_bits());
+            assert_eq!(p.b.to_bits(), expected.b.to_bits());
+            assert_eq!(p.c.to_bits(), expected.c.to_bits());
+            assert_eq!(p.d.to_bits(), expected.d.to_bits());
+        }
+    }
+    
+    #[test]
+    fn without_translation_keeps_rotation() {
+        let rotation = Rotation::new(0.6, -2.1).to_matrix();
+        let view = matrix_mul(&rotation, &Translation::new(5., -3., 12.).to_matrix());
+        
+        assert_matrix_near(&without_translation(&view), &rotation);
+        assert_eq!(without_translation(&rotation), rotation);
+    }
 }
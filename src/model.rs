@@ -2,13 +2,14 @@
 //! with textures and a camera.
 
 use std::rc::Rc;
-use luminance::framebuffer::Framebuffer;
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
 use luminance::linear::M44;
+use luminance::pipeline::{Gpu, ShadingGate};
 use luminance::vertex;
 use luminance::pixel::RGB32F;
 use luminance::tess::Tess;
 use luminance::texture::{Dim2, Flat, Texture};
-use luminance_glfw::GLFWDevice;
+use luminance_glfw::{Device, GLFWDevice};
 use png::OutputInfo;
 use camera::Camera;
 use maths::{ToMatrix, Translation};
@@ -66,11 +67,26 @@ pub trait Drawable {
     type Uniform;
     */
     
-    /// Perform the draw call.
-    fn draw(&self,
-            device: &mut GLFWDevice,
-            render_target: &Framebuffer<Flat, Dim2, (), ()>,
-            camera: &Camera);
+    /// Draw within a pipeline that is already open, so that several
+    /// drawables can share one framebuffer and depth buffer.
+    fn draw_in(&self, gpu: &Gpu, shade_gate: &ShadingGate, camera: &Camera);
+    
+    /// Render to the target without presenting the frame, so that
+    /// several passes can be combined in one `GLFWDevice::draw`.
+    /// This opens a new pipeline, clearing the target.
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2>;
+    
+    /// Perform the draw call, presenting the frame.
+    fn draw<CS, DS>(&self,
+                    device: &mut GLFWDevice,
+                    render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                    camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        device.draw(|| self.render(render_target, camera));
+    }
     /*
     fn draw(&self,
             device: &mut GLFWDevice,
@@ -1,34 +1,203 @@
 //! Utilities for managing shared data, such as images.
 
-use std::fs::File;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
 use std::rc::Rc;
 use luminance::pixel::RGB32F;
 use luminance::texture::{Dim2, Flat, MagFilter, MinFilter, Sampler, Texture};
 use png::{self, Decoder, OutputInfo};
+use terrain::{Block, Face};
+
+/// The terrain atlases, in the order blocks refer to them.
+/// See `Block::atlas`.
+pub const TERRAIN_ATLASES: [&str; 1] = ["data/atlas.png"];
+
+/// The image drawn at the center of the screen.
+/// Black texels are transparent.
+pub const CROSSHAIR: &str = "data/crosshair.png";
+
+/// The six faces of the skybox side by side, in the order
+/// +X, -X, +Y, -Y, +Z and -Z. See `Skybox`.
+pub const SKYBOX: &str = "data/skybox.png";
+
+/// The width and height of an atlas tile, in pixels.
+pub const TILE_SIZE: u32 = 16;
+
+// Block faces that do not use the block's own tile, as
+// `(block, face, (column, row))`. Grass has a loam bottom
+// and sides with a grassy edge.
+const FACE_TILES: [(Block, Face, (u32, u32)); 5] = [
+    (Block::Grass, Face::Bottom, (1, 0)),
+    (Block::Grass, Face::Back, (27, 0)),
+    (Block::Grass, Face::Front, (27, 0)),
+    (Block::Grass, Face::Left, (27, 0)),
+    (Block::Grass, Face::Right, (27, 0)),
+];
+
+/// Where the tiles of each block face are in a terrain atlas.
+///
+/// Tiles are `TILE_SIZE` pixels square, in a grid. Unless set with
+/// `with_face`, every face of a block uses the tile at its id less
+/// one, counting along the rows, or the first tile of its animation.
+/// Animation frames follow the first tile along its row.
+#[derive(Clone, Debug)]
+pub struct AtlasLayout {
+    columns: u32,
+    rows: u32,
+    faces: HashMap<(Block, Face), (u32, u32)>,
+}
+
+impl AtlasLayout {
+    /// Create the default layout for an atlas of
+    /// this size, in pixels.
+    /// # Panics
+    /// Panics if the atlas is not a whole number of tiles
+    /// in each dimension, since its tiles would not line up.
+    pub fn new(width: u32, height: u32) -> AtlasLayout {
+        assert!(width % TILE_SIZE == 0 && height % TILE_SIZE == 0,
+                "a {}x{} atlas is not a whole number of {}x{} tiles",
+                width, height, TILE_SIZE, TILE_SIZE);
+        
+        AtlasLayout {
+            columns: width / TILE_SIZE,
+            rows: height / TILE_SIZE,
+            faces: HashMap::new(),
+        }
+    }
+    
+    /// Use the tile at `(column, row)` for one face of a block.
+    pub fn with_face(mut self, block: Block, face: Face, tile: (u32, u32)) -> AtlasLayout {
+        self.faces.insert((block, face), tile);
+        self
+    }
+    
+    /// Get the width of the atlas, in tiles.
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+    
+    /// Get the height of the atlas, in tiles.
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+    
+    /// Get the `(column, row)` of the tile for a face of a block.
+    pub fn tile(&self, block: &Block, face: Face) -> (u32, u32) {
+        if let Some(&tile) = self.faces.get(&(*block, face)) {
+            return tile;
+        }
+        
+        let index = match block.animation() {
+            Some(anim) => anim.first_tile,
+            None => block.id() as u32 - 1,
+        };
+        
+        (index % self.columns, index / self.columns)
+    }
+    
+    /// Get the index of a tile, counting along the rows.
+    pub fn index(&self, tile: (u32, u32)) -> u32 {
+        tile.0 + tile.1 * self.columns
+    }
+}
 
 /// A simple resource manager that can load and provide resources.
 pub struct Resources {
-    terrain_tex: Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>,
+    terrain_atlases: Vec<Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>>,
+    terrain_layouts: Vec<AtlasLayout>,
+    crosshair_tex: Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>,
+    skybox_tex: Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>,
 }
 
 impl Resources {
-    /// Create a new resource manager.
+    /// Create a new resource manager, reading the images
+    /// at `TERRAIN_ATLASES`, `CROSSHAIR` and `SKYBOX`.
     /// # Panics
     /// This constructor panics if the resources
-    /// could not be loaded from disk.
+    /// could not be loaded from disk, or a terrain
+    /// atlas is not a whole number of tiles in size.
     pub fn new() -> Resources {
+        let atlases: Vec<_> = TERRAIN_ATLASES.iter().map(|path| fs::read(path).unwrap()).collect();
+        let atlases: Vec<&[u8]> = atlases.iter().map(|a| &a[..]).collect();
+        
+        Self::from_bytes(&atlases, &fs::read(CROSSHAIR).unwrap(), &fs::read(SKYBOX).unwrap())
+    }
+    
+    /// Create a resource manager from PNG images already in memory,
+    /// such as ones embedded with `include_bytes!`. `atlases` are
+    /// in the order blocks refer to them, like `TERRAIN_ATLASES`.
+    /// # Panics
+    /// This constructor panics if an image can not be decoded
+    /// or is not 8 bit RGB, or a terrain atlas is not a whole
+    /// number of tiles in size.
+    pub fn from_bytes(atlases: &[&[u8]], crosshair: &[u8], skybox: &[u8]) -> Resources {
+        let terrain_atlases: Vec<_> = atlases.iter().map(|&data| {
+            Rc::new(Self::load_texture(data))
+        }).collect();
+        
+        let terrain_layouts = terrain_atlases.iter().enumerate().map(|(i, atlas)| {
+            let layout = AtlasLayout::new(atlas.1.width, atlas.1.height);
+            
+            FACE_TILES.iter()
+                      .filter(|f| f.0.atlas() == i)
+                      .fold(layout, |l, &(block, face, tile)| l.with_face(block, face, tile))
+        }).collect();
+        
+        let crosshair_tex = Rc::new(Self::load_texture(crosshair));
+        let skybox_tex = Rc::new(Self::load_texture(skybox));
+        
         Resources {
-            terrain_tex: Rc::new(Self::load_texture(File::open("data/atlas.png").unwrap())),
+            terrain_atlases,
+            terrain_layouts,
+            crosshair_tex,
+            skybox_tex,
         }
     }
     
-    /// Get terrain texture.
+    /// Get the first terrain atlas.
     pub fn terrain_tex(&self) -> Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)> {
-        self.terrain_tex.clone()
+        self.terrain_atlases[0].clone()
     }
     
-    fn load_texture(file: File) -> (Texture<Flat, Dim2, RGB32F>, OutputInfo) {
-        let png_decoder = Decoder::new(file);
+    /// Get every terrain atlas, in the order of `TERRAIN_ATLASES`.
+    pub fn terrain_atlases(&self) -> &[Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>] {
+        &self.terrain_atlases
+    }
+    
+    /// Get the tile layout of each terrain atlas,
+    /// in the order of `TERRAIN_ATLASES`.
+    pub fn terrain_layouts(&self) -> &[AtlasLayout] {
+        &self.terrain_layouts
+    }
+    
+    /// Get the crosshair image.
+    pub fn crosshair_tex(&self) -> Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)> {
+        self.crosshair_tex.clone()
+    }
+    
+    /// Get the skybox image.
+    pub fn skybox_tex(&self) -> Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)> {
+        self.skybox_tex.clone()
+    }
+    
+    fn load_texture<R: Read>(source: R) -> (Texture<Flat, Dim2, RGB32F>, OutputInfo) {
+        let (image, png_info) = Self::decode_png(source);
+        
+        let mut sampler = Sampler::default();
+        sampler.min_filter = MinFilter::Nearest;
+        sampler.mag_filter = MagFilter::Nearest;
+        
+        let tex = Texture::<Flat, Dim2, RGB32F>::new(
+                [png_info.width, png_info.height], 0, &sampler).unwrap();
+        tex.upload(false, &image);
+        
+        (tex, png_info)
+    }
+    
+    // Decode an 8 bit RGB PNG image to texels.
+    fn decode_png<R: Read>(source: R) -> (Vec<(f32, f32, f32)>, OutputInfo) {
+        let png_decoder = Decoder::new(source);
         let (png_info, mut png_reader) = png_decoder.read_info().unwrap();
         assert_eq!(png_info.color_type, png::ColorType::RGB);
         assert_eq!(png_info.bit_depth, png::BitDepth::Eight);
@@ -47,14 +216,24 @@ impl Resources {
                         png_data[x + 2] as f32 / 255.));
         }
         
-        let mut sampler = Sampler::default();
-        sampler.min_filter = MinFilter::Nearest;
-        sampler.mag_filter = MagFilter::Nearest;
-        
-        let tex = Texture::<Flat, Dim2, RGB32F>::new(
-                [png_info.width, png_info.height], 0, &sampler).unwrap();
-        tex.upload(false, &image);
+        (image, png_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    #[should_panic(expected = "not a whole number")]
+    fn partial_tile_atlas_panics() {
+        AtlasLayout::new(17, 16);
+    }
+    
+    #[test]
+    fn whole_tile_atlas_counts_tiles() {
+        let layout = AtlasLayout::new(TILE_SIZE * 4, TILE_SIZE * 2);
         
-        (tex, png_info)
+        assert_eq!((layout.columns(), layout.rows()), (4, 2));
     }
 }
@@ -1,27 +1,173 @@
 //! Shader uniform interfaces and utilities.
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::MAIN_SEPARATOR;
+use luminance::shader::program::ProgramError;
 
 const SHADER_DIR: &str = "shaders";
 const EXTENTION: &str = ".glsl";
 
+/// Where the source of a vertex and fragment shader pair comes from.
+#[derive(Clone, Copy, Debug)]
+pub enum ShaderSource<'s> {
+    /// Shader names, loaded from the `shaders` directory.
+    /// See `load_shader_text`.
+    Files { vs: &'s str, fs: &'s str },
+    
+    /// Shader source supplied directly, requiring no filesystem access.
+    Inline { vs: &'s str, fs: &'s str },
+}
+
+impl<'s> ShaderSource<'s> {
+    /// Get the vertex and fragment shader source.
+    /// See `load_shader_text` for the errors.
+    pub fn text(&self) -> io::Result<(String, String)> {
+        match *self {
+            ShaderSource::Files { vs, fs } => load_shader_text(vs, fs),
+            ShaderSource::Inline { vs, fs } => Ok((vs.to_string(), fs.to_string())),
+        }
+    }
+}
+
+/// The terrain shaders, read from the `shaders` directory at runtime.
+#[cfg(not(feature = "embed_shaders"))]
+pub const TERRAIN: ShaderSource<'static> = ShaderSource::Files {
+    vs: "vs",
+    fs: "fs",
+};
+
+/// The terrain shaders, embedded in the binary.
+#[cfg(feature = "embed_shaders")]
+pub const TERRAIN: ShaderSource<'static> = ShaderSource::Inline {
+    vs: include_str!("../shaders/vs.glsl"),
+    fs: include_str!("../shaders/fs.glsl"),
+};
+
+/// The shaders for stretching an offscreen image over the window,
+/// read from the `shaders` directory at runtime.
+#[cfg(not(feature = "embed_shaders"))]
+pub const BLIT: ShaderSource<'static> = ShaderSource::Files {
+    vs: "blit_vs",
+    fs: "blit_fs",
+};
+
+/// The shaders for stretching an offscreen image over the window,
+/// embedded in the binary.
+#[cfg(feature = "embed_shaders")]
+pub const BLIT: ShaderSource<'static> = ShaderSource::Inline {
+    vs: include_str!("../shaders/blit_vs.glsl"),
+    fs: include_str!("../shaders/blit_fs.glsl"),
+};
+
+/// The sky shaders, read from the `shaders` directory at runtime.
+#[cfg(not(feature = "embed_shaders"))]
+pub const SKY: ShaderSource<'static> = ShaderSource::Files {
+    vs: "sky_vs",
+    fs: "sky_fs",
+};
+
+/// The sky shaders, embedded in the binary.
+#[cfg(feature = "embed_shaders")]
+pub const SKY: ShaderSource<'static> = ShaderSource::Inline {
+    vs: include_str!("../shaders/sky_vs.glsl"),
+    fs: include_str!("../shaders/sky_fs.glsl"),
+};
+
+/// The skybox shaders, read from the `shaders` directory at runtime.
+#[cfg(not(feature = "embed_shaders"))]
+pub const SKYBOX: ShaderSource<'static> = ShaderSource::Files {
+    vs: "skybox_vs",
+    fs: "skybox_fs",
+};
+
+/// The skybox shaders, embedded in the binary.
+#[cfg(feature = "embed_shaders")]
+pub const SKYBOX: ShaderSource<'static> = ShaderSource::Inline {
+    vs: include_str!("../shaders/skybox_vs.glsl"),
+    fs: include_str!("../shaders/skybox_fs.glsl"),
+};
+
+/// The HUD overlay shaders, read from the `shaders` directory at runtime.
+#[cfg(not(feature = "embed_shaders"))]
+pub const HUD: ShaderSource<'static> = ShaderSource::Files {
+    vs: "hud_vs",
+    fs: "hud_fs",
+};
+
+/// The HUD overlay shaders, embedded in the binary.
+#[cfg(feature = "embed_shaders")]
+pub const HUD: ShaderSource<'static> = ShaderSource::Inline {
+    vs: include_str!("../shaders/hud_vs.glsl"),
+    fs: include_str!("../shaders/hud_fs.glsl"),
+};
+
 /// Load shader source from shader names.
 /// **Note:** the arguments take the filename, not the path.
 /// do not include the full path in the arguments.
-pub fn load_shader_text(vertex: &str, fragment: &str) -> (String, String) {
-    let mut vs = String::new();
-    let mut fs = String::new();
-    
+///
+/// If a file can not be read, the error's message
+/// begins with the path of the file.
+pub fn load_shader_text(vertex: &str, fragment: &str) -> io::Result<(String, String)> {
     let mut dir = SHADER_DIR.to_string();
     dir.push(MAIN_SEPARATOR);
     
-    File::open(dir.clone() + vertex + EXTENTION).unwrap()
-        .read_to_string(&mut vs).unwrap();
+    let vs = read_shader(&(dir.clone() + vertex + EXTENTION))?;
+    let fs = read_shader(&(dir + fragment + EXTENTION))?;
+    
+    Ok((vs, fs))
+}
+
+/// Why a shader program could not be built.
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The source could not be read. See `load_shader_text`.
+    Io(io::Error),
+    
+    /// The source failed to compile or link.
+    Program(ProgramError),
+}
+
+impl From<io::Error> for ShaderError {
+    fn from(e: io::Error) -> ShaderError {
+        ShaderError::Io(e)
+    }
+}
+
+impl From<ProgramError> for ShaderError {
+    fn from(e: ProgramError) -> ShaderError {
+        ShaderError::Program(e)
+    }
+}
+
+// Read a whole shader file, naming it in any error.
+fn read_shader(path: &str) -> io::Result<String> {
+    let mut text = String::new();
+    
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn missing_shader_names_path() {
+        let err = load_shader_text("nope", "nope").unwrap_err();
+        let path = format!("{}{}nope{}", SHADER_DIR, MAIN_SEPARATOR, EXTENTION);
         
-    File::open(dir + fragment + EXTENTION).unwrap()
-        .read_to_string(&mut fs).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&path), "{}", err);
+    }
     
-    (vs, fs)
+    #[test]
+    fn inline_source_needs_no_files() {
+        let source = ShaderSource::Inline { vs: "vertex", fs: "fragment" };
+        
+        assert_eq!(source.text().unwrap(), ("vertex".to_string(), "fragment".to_string()));
+    }
 }
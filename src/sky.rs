@@ -0,0 +1,171 @@
+//! A sky gradient with a sun disc, drawn behind everything else.
+
+use luminance::depth_test::DepthTest;
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
+use luminance::linear::M44;
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::shader::program::{Program, ProgramError, Uniform, UniformBuilder,
+                                 UniformInterface, UniformWarning};
+use luminance::tess::{Mode, Tess};
+use luminance::texture::{Dim2, Flat};
+use camera::Camera;
+use maths::{self, ToMatrix};
+use model::Drawable;
+use shader::ShaderSource;
+use terrain::CLEAR_COLOR;
+
+const ZENITH_COLOR: [f32; 3] = [0.1, 0.45, 0.85];
+const DEFAULT_SUN_SIZE: f32 = 0.03;
+const DEFAULT_SUN_COLOR: [f32; 3] = [1.0, 0.95, 0.8];
+
+/// A sky that fades from the clear color at the horizon to a deeper
+/// blue overhead, with a glowing sun disc.
+pub struct Sky {
+    program: Program<(), (), SkyUniforms>,
+    quad: Tess<()>,
+    sun_direction: [f32; 3],
+    sun_size: f32,
+    sun_color: [f32; 3],
+}
+
+impl Sky {
+    /// Create a sky using the shader pair from `shaders`.
+    /// # Panics
+    /// Panics if the shaders fail to load.
+    pub fn new(shaders: ShaderSource) -> Sky {
+        let (vs, fs) = shaders.text().unwrap();
+        let (program, warnings) = Program::from_strings(None, &vs, None, &fs).unwrap();
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        Sky {
+            program,
+            quad: Tess::attributeless(Mode::TriangleStrip, 4),
+            sun_direction: normalize([0.3, 0.8, 0.5]),
+            sun_size: DEFAULT_SUN_SIZE,
+            sun_color: DEFAULT_SUN_COLOR,
+        }
+    }
+    
+    /// Set the direction pointing toward the sun. It does
+    /// not need to be normalized, but must not be zero.
+    pub fn set_sun_direction(&mut self, direction: [f32; 3]) {
+        self.sun_direction = normalize(direction);
+    }
+    
+    /// Get the normalized direction pointing toward the sun.
+    pub fn sun_direction(&self) -> [f32; 3] {
+        self.sun_direction
+    }
+    
+    /// Set the angular radius of the sun disc, **in radians**.
+    /// The glow around the disc scales with it.
+    pub fn set_sun_size(&mut self, size: f32) {
+        self.sun_size = size.max(0.);
+    }
+    
+    /// Get the angular radius of the sun disc, **in radians**.
+    pub fn sun_size(&self) -> f32 {
+        self.sun_size
+    }
+    
+    /// Set the color of the sun disc and its glow.
+    pub fn set_sun_color(&mut self, color: [f32; 3]) {
+        self.sun_color = color;
+    }
+    
+    /// Get the color of the sun disc and its glow.
+    pub fn sun_color(&self) -> [f32; 3] {
+        self.sun_color
+    }
+}
+
+impl Drawable for Sky {
+    fn draw_in(&self, _gpu: &Gpu, shade_gate: &ShadingGate, camera: &Camera) {
+        // Only the rotation of the view matters for view rays.
+        let view = maths::without_translation(&camera.to_matrix());
+        
+        let inverse = match maths::matrix_inverse(&maths::matrix_mul(camera.projection_matrix(), &view)) {
+            Some(m) => m,
+            None => return,
+        };
+        
+        shade_gate.shade(&self.program, |render_gate, uniforms| {
+            uniforms.inverse_view_projection.update(inverse);
+            uniforms.horizon_color.update([CLEAR_COLOR[0], CLEAR_COLOR[1], CLEAR_COLOR[2]]);
+            uniforms.zenith_color.update(ZENITH_COLOR);
+            uniforms.sun_direction.update(self.sun_direction);
+            uniforms.sun_size.update(self.sun_size);
+            uniforms.sun_color.update(self.sun_color);
+            
+            // Without the depth test, nothing is written to the
+            // depth buffer, so the terrain always covers the sky.
+            let render_state = RenderState::default()
+                               .set_depth_test(DepthTest::Disabled);
+            render_gate.render(render_state, |tess_gate| {
+                tess_gate.render((&self.quad).into());
+            });
+        });
+    }
+    
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        entry(|gpu| {
+            pipeline(render_target, CLEAR_COLOR, |shade_gate| {
+                self.draw_in(&gpu, &shade_gate, camera);
+            });
+        });
+    }
+}
+
+/// The sky's uniform interface.
+struct SkyUniforms {
+    // Maps clip space back to view rays.
+    inverse_view_projection: Uniform<M44>,
+    
+    // Sky color at the horizon.
+    horizon_color: Uniform<[f32; 3]>,
+    
+    // Sky color straight up.
+    zenith_color: Uniform<[f32; 3]>,
+    
+    // Normalized direction toward the sun.
+    sun_direction: Uniform<[f32; 3]>,
+    
+    // Angular radius of the sun disc.
+    sun_size: Uniform<f32>,
+    
+    // Color of the sun disc and glow.
+    sun_color: Uniform<[f32; 3]>,
+}
+
+impl UniformInterface for SkyUniforms {
+    fn uniform_interface(builder: UniformBuilder)
+            -> Result<(SkyUniforms, Vec<UniformWarning>), ProgramError> {
+        
+        let inverse_view_projection = builder.ask("inverse_view_projection").unwrap();
+        let horizon_color = builder.ask("horizon_color").unwrap();
+        let zenith_color = builder.ask("zenith_color").unwrap();
+        let sun_direction = builder.ask("sun_direction").unwrap();
+        let sun_size = builder.ask("sun_size").unwrap();
+        let sun_color = builder.ask("sun_color").unwrap();
+        
+        Ok((SkyUniforms {
+            inverse_view_projection,
+            horizon_color,
+            zenith_color,
+            sun_direction,
+            sun_size,
+            sun_color,
+        }, Vec::new()))
+    }
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    
+    [v[0] / len, v[1] / len, v[2] / len]
+}
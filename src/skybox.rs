@@ -0,0 +1,103 @@
+//! A textured cube around the camera, drawn behind everything else.
+
+use std::rc::Rc;
+use luminance::depth_test::DepthTest;
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
+use luminance::linear::M44;
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::pixel::RGB32F;
+use luminance::shader::program::{Program, ProgramError, Uniform, UniformBuilder,
+                                 UniformInterface, UniformWarning};
+use luminance::tess::{Mode, Tess};
+use luminance::texture::{Dim2, Flat, Texture};
+use png::OutputInfo;
+use camera::Camera;
+use maths::{self, ToMatrix};
+use model::Drawable;
+use resources::Resources;
+use shader::ShaderSource;
+use terrain::CLEAR_COLOR;
+
+// Two triangles for each of the six faces.
+const VERTEX_COUNT: usize = 36;
+
+/// A skybox, drawn as six textured quads facing inward. The faces
+/// are side by side in one image, see `resources::SKYBOX`.
+///
+/// Only the rotation of the camera is used, so the
+/// cube appears infinitely far away.
+pub struct Skybox {
+    program: Program<(), (), SkyboxUniforms>,
+    cube: Tess<()>,
+    tex: Rc<(Texture<Flat, Dim2, RGB32F>, OutputInfo)>,
+}
+
+impl Skybox {
+    /// Create a skybox using the image from `resources`
+    /// and the shader pair from `shaders`.
+    /// # Panics
+    /// Panics if the shaders fail to load.
+    pub fn new(resources: &Resources, shaders: ShaderSource) -> Skybox {
+        let (vs, fs) = shaders.text().unwrap();
+        let (program, warnings) = Program::from_strings(None, &vs, None, &fs).unwrap();
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        Skybox {
+            program,
+            cube: Tess::attributeless(Mode::Triangle, VERTEX_COUNT),
+            tex: resources.skybox_tex(),
+        }
+    }
+}
+
+impl Drawable for Skybox {
+    fn draw_in(&self, gpu: &Gpu, shade_gate: &ShadingGate, camera: &Camera) {
+        gpu.bind_texture(&self.tex.0);
+        
+        let view = maths::without_translation(&camera.to_matrix());
+        let view_projection = maths::matrix_mul(camera.projection_matrix(), &view);
+        
+        shade_gate.shade(&self.program, |render_gate, uniforms| {
+            uniforms.view_projection.update(view_projection);
+            
+            // Without the depth test, nothing is written to the
+            // depth buffer, so the terrain always covers the skybox.
+            let render_state = RenderState::default()
+                               .set_depth_test(DepthTest::Disabled);
+            render_gate.render(render_state, |tess_gate| {
+                tess_gate.render((&self.cube).into());
+            });
+        });
+    }
+    
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        entry(|gpu| {
+            pipeline(render_target, CLEAR_COLOR, |shade_gate| {
+                self.draw_in(&gpu, &shade_gate, camera);
+            });
+        });
+    }
+}
+
+/// The skybox's uniform interface.
+struct SkyboxUniforms {
+    // The projection times the view rotation.
+    view_projection: Uniform<M44>,
+}
+
+impl UniformInterface for SkyboxUniforms {
+    fn uniform_interface(builder: UniformBuilder)
+            -> Result<(SkyboxUniforms, Vec<UniformWarning>), ProgramError> {
+        
+        let view_projection = builder.ask("view_projection").unwrap();
+        
+        Ok((SkyboxUniforms {
+            view_projection,
+        }, Vec::new()))
+    }
+}
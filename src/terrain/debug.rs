@@ -0,0 +1,91 @@
+//! A single textured triangle, drawn with the terrain shaders.
+//! For debugging only: with no meshing or streaming involved,
+//! it isolates shader and camera bugs from terrain bugs.
+
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::shader::program::Program;
+use luminance::tess::{Mode, Tess, TessVertices};
+use luminance::texture::{Dim2, Flat};
+use camera::Camera;
+use maths::{self, ToMatrix};
+use model::Drawable;
+use resources::Resources;
+use shader::ShaderSource;
+use super::{mesh_gen, Block, Face, Terrain, Uniforms, Vertex, CLEAR_COLOR, DEFAULT_SUN_DIRECTION};
+
+// Facing the camera's starting direction, a few blocks away.
+const POSITIONS: [[f32; 3]; 3] = [
+    [-0.5, -0.5, -3.0],
+    [ 0.5, -0.5, -3.0],
+    [ 0.0,  0.5, -3.0],
+];
+
+/// A textured triangle in front of the camera's starting position.
+pub struct DebugTriangle<'a> {
+    shader: Program<Vertex, (), Uniforms>,
+    resources: &'a Resources,
+    tess: Tess<Vertex>,
+}
+
+impl<'a> DebugTriangle<'a> {
+    /// Create the triangle, textured with the limestone tile.
+    /// # Panics
+    /// This constructor panics if shaders fail to load.
+    pub fn new(resources: &'a Resources, shaders: ShaderSource) -> DebugTriangle<'a> {
+        let (shader, warnings) = Terrain::load_shaders(shaders).unwrap();
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        let uv = mesh_gen::tex_coords(&Block::Limestone, Face::Front, &resources.terrain_layouts()[0]);
+        let id = Block::Limestone.id() as u32;
+        
+        let vertices: [Vertex; 3] = [
+            (POSITIONS[0], uv.0, 1, id, 0, 0, 0., [0.; 3], 1., 1.),
+            (POSITIONS[1], uv.3, 1, id, 0, 0, 0., [0.; 3], 1., 1.),
+            (POSITIONS[2], uv.2, 1, id, 0, 0, 0., [0.; 3], 1., 1.),
+        ];
+        
+        DebugTriangle {
+            shader,
+            resources,
+            tess: Tess::new(Mode::Triangle, TessVertices::Fill(&vertices), None),
+        }
+    }
+}
+
+impl<'a> Drawable for DebugTriangle<'a> {
+    fn draw_in(&self, gpu: &Gpu, shade_gate: &ShadingGate, camera: &Camera) {
+        gpu.bind_texture(&self.resources.terrain_tex().0);
+        shade_gate.shade(&self.shader, |render_gate, uniforms| {
+            uniforms.model_matrix.update(maths::IDENTITY);
+            uniforms.view_matrix.update(camera.to_matrix());
+            uniforms.projection_matrix.update(*camera.projection_matrix());
+            uniforms.texel_lod_bias.update(0.);
+            uniforms.time.update(0.);
+            uniforms.opacity.update(1.);
+            uniforms.fog_color.update([0.; 3]);
+            uniforms.fog_distance.update(0.);
+            uniforms.sun_direction.update(DEFAULT_SUN_DIRECTION);
+            
+            // Draw both sides, so winding bugs do not hide it.
+            let render_state = RenderState::default()
+                               .set_face_culling(None);
+            render_gate.render(render_state, |tess_gate| {
+                tess_gate.render((&self.tess).into());
+            });
+        });
+    }
+    
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        entry(|gpu| {
+            pipeline(render_target, CLEAR_COLOR, |shade_gate| {
+                self.draw_in(&gpu, &shade_gate, camera);
+            });
+        });
+    }
+}
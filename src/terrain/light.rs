@@ -0,0 +1,189 @@
+//! Colored block light, spread from emitting blocks
+//! through the air around them, and sky light.
+
+use std::collections::{HashMap, VecDeque};
+use super::SECTOR_SIZE;
+use super::voxel::{Block, BlockList, SectorSpaceCoords};
+
+/// The light level of each color channel, from `0` to `MAX_LIGHT`.
+pub type LightColor = [u8; 3];
+
+/// The brightest light level. Light fades by one level per
+/// block it travels, in every channel alike, so this is also
+/// the farthest light can reach.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Light a sector with sunlight from above, storing the sky light
+/// level of every block in the `BlockList`.
+///
+/// If `open_to_sky`, air open to the top of the sector gets
+/// `MAX_LIGHT`, straight down to the first block in the way. From
+/// there, light spreads sideways and down through air and transparent
+/// blocks, fading by one level per block. Otherwise the whole sector
+/// is dark. Light does not yet cross into neighboring sectors.
+/// See `open_to_sky`.
+pub fn propagate_sky_light(blocks: &mut BlockList, open_to_sky: bool) {
+    let mut queue = VecDeque::new();
+    let size = SECTOR_SIZE as u8;
+    
+    for x in 0..size {
+        for z in 0..size {
+            let mut open = open_to_sky;
+            
+            for y in (0..size).rev() {
+                let coord = SectorSpaceCoords::new(x, y, z);
+                open = open && blocks.get(coord).is_air();
+                
+                if open {
+                    blocks.set_light(coord, MAX_LIGHT);
+                    queue.push_back(coord);
+                } else {
+                    blocks.set_light(coord, 0);
+                }
+            }
+        }
+    }
+    
+    while let Some(coord) = queue.pop_front() {
+        let faded = blocks.light_at(coord).saturating_sub(1);
+        if faded == 0 {
+            continue;
+        }
+        
+        let neighbors = [coord.back(), coord.front(), coord.bottom(),
+                         coord.left(), coord.right()];
+        
+        for n in neighbors.iter().filter_map(|&n| n) {
+            let block = blocks.get(n);
+            if !block.is_air() && !block.is_transparent() {
+                continue;
+            }
+            
+            if blocks.light_at(n) < faded {
+                blocks.set_light(n, faded);
+                queue.push_back(n);
+            }
+        }
+    }
+}
+
+/// Determine whether sunlight reaches the top of the sector at
+/// these sector coordinates. Sectors below the surface layer are
+/// under the ground, so their caves are left dark.
+pub fn open_to_sky(sector: (i32, i32, i32)) -> bool {
+    sector.1 >= 0
+}
+
+/// The light level at every block of a sector.
+pub struct LightMap(Vec<LightColor>);
+
+impl LightMap {
+    /// Spread light from every block with an entry in `emitters`
+    /// through the air of a sector. Light does not yet cross into
+    /// neighboring sectors. Returns `None` if nothing emits light.
+    pub fn propagate(blocks: &BlockList, emitters: &HashMap<Block, LightColor>) -> Option<LightMap> {
+        if emitters.is_empty() {
+            return None;
+        }
+        
+        let mut light = LightMap(Vec::new());
+        let mut queue = VecDeque::new();
+        
+        for (coord, block) in blocks {
+            let color = emitters.get(block).cloned().unwrap_or([0; 3]);
+            light.0.push(color);
+            
+            if color != [0; 3] {
+                queue.push_back(coord);
+            }
+        }
+        
+        if queue.is_empty() {
+            return None;
+        }
+        
+        while let Some(coord) = queue.pop_front() {
+            let here = light.get(coord);
+            let faded = [here[0].saturating_sub(1),
+                         here[1].saturating_sub(1),
+                         here[2].saturating_sub(1)];
+            
+            if faded == [0; 3] {
+                continue;
+            }
+            
+            let neighbors = [coord.back(), coord.front(), coord.top(),
+                             coord.bottom(), coord.left(), coord.right()];
+            
+            for n in neighbors.iter().filter_map(|&n| n) {
+                if !blocks.get(n).is_air() {
+                    continue;
+                }
+                
+                let old = light.get(n);
+                let new = [old[0].max(faded[0]), old[1].max(faded[1]), old[2].max(faded[2])];
+                
+                if new != old {
+                    light.0[BlockList::index(n)] = new;
+                    queue.push_back(n);
+                }
+            }
+        }
+        
+        Some(light)
+    }
+    
+    /// Get the light level at a position in sector coords.
+    pub fn get(&self, pos: SectorSpaceCoords) -> LightColor {
+        self.0[BlockList::index(pos)]
+    }
+    
+    /// Get the light level at a position as a color
+    /// with channels from `0` to `1`.
+    pub fn get_f32(&self, pos: SectorSpaceCoords) -> [f32; 3] {
+        let c = self.get(pos);
+        let max = MAX_LIGHT as f32;
+        
+        [c[0] as f32 / max, c[1] as f32 / max, c[2] as f32 / max]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn open_sky_gets_max_light() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((0, 0, 0), (31, 9, 31), Block::Limestone);
+        propagate_sky_light(&mut blocks, true);
+        
+        assert_eq!(blocks.light_at(SectorSpaceCoords::new(4, 10, 4)), MAX_LIGHT);
+        assert_eq!(blocks.light_at(SectorSpaceCoords::new(4, 31, 4)), MAX_LIGHT);
+    }
+    
+    #[test]
+    fn buried_air_gets_no_light() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((0, 0, 0), (31, 20, 31), Block::Limestone);
+        blocks.set(SectorSpaceCoords::new(8, 10, 8), Block::Air);
+        propagate_sky_light(&mut blocks, true);
+        
+        assert_eq!(blocks.light_at(SectorSpaceCoords::new(8, 10, 8)), 0);
+    }
+    
+    #[test]
+    fn closed_sector_is_dark() {
+        let mut blocks = BlockList::new_air();
+        propagate_sky_light(&mut blocks, false);
+        
+        assert!(blocks.into_iter().all(|(c, _)| blocks.light_at(c) == 0));
+    }
+    
+    #[test]
+    fn only_surface_sectors_are_open_to_sky() {
+        assert!(open_to_sky((5, 0, -3)));
+        assert!(open_to_sky((0, 2, 0)));
+        assert!(!open_to_sky((0, -1, 0)));
+    }
+}
@@ -1,8 +1,10 @@
 //! This module contains the logic for creating tesselations
 //! from `Sector`.
 
-use png::OutputInfo;
+use std::cmp::Ordering;
+use resources::AtlasLayout;
 use super::{Position, UV, Vertex, SECTOR_SIZE};
+use super::light::{LightMap, MAX_LIGHT};
 use super::voxel::{AdjacentSectors, Block, BlockList, SectorSpaceCoords};
 
 /*
@@ -26,6 +28,9 @@ const POSITIONS: [Position; 8] = [
     [0.0, 0.0, 1.0],
 ];
 
+// The vertices added by `generate_face`, two triangles.
+const VERTICES_PER_FACE: usize = 6;
+
 /*
 const UVS: (UV, UV, UV, UV) = (
     [1.0, 1.0],
@@ -35,10 +40,10 @@ const UVS: (UV, UV, UV, UV) = (
 );
 */
 
-const TILE_SIZE: f32 = 16.;
-
-#[derive(Clone, Copy)]
-enum Face {
+/// A face of a block. The discriminant is the face number
+/// passed to the terrain shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
     Back,
     Front,
     Top,
@@ -47,52 +52,327 @@ enum Face {
     Right,
 }
 
-/// Generate the mesh for a `BlockList`.
-pub fn generate_block_vertices(blocks: &BlockList, adjacent: &AdjacentSectors,
-                               texture_info: &OutputInfo) -> Vec<Vertex> {
+impl Face {
+    /// Get the unit vector pointing out of the face.
+    /// The terrain shader lights faces with the same table.
+    pub fn normal(&self) -> [i32; 3] {
+        use self::Face::*;
+        
+        match *self {
+            Back => [0, 0, -1],
+            Front => [0, 0, 1],
+            Top => [0, 1, 0],
+            Bottom => [0, -1, 0],
+            Left => [-1, 0, 0],
+            Right => [1, 0, 0],
+        }
+    }
+}
+
+/// Bring the cached visible faces of each block up to date. The bit
+/// `1 << face` of a block's mask is set if that face should be drawn,
+/// with faces in the order back, front, top, bottom, left, right.
+///
+/// If `masks` is empty, every block is computed. Otherwise only the
+/// `dirty` blocks are. Faces bordering a missing neighbor are only
+/// visible if `draw_frontier` is set.
+pub fn update_face_masks(masks: &mut Vec<u8>, dirty: &[SectorSpaceCoords], blocks: &BlockList,
+                         adjacent: &AdjacentSectors, draw_frontier: bool) {
+    if masks.is_empty() {
+        masks.extend(blocks.into_iter().map(|i| face_mask(i, blocks, adjacent, draw_frontier)));
+    } else {
+        for &coord in dirty {
+            masks[BlockList::index(coord)] = face_mask((coord, blocks.get(coord)), blocks,
+                                                       adjacent, draw_frontier);
+        }
+    }
+}
+
+/// Counts of the mesh that `generate_block_vertices`, or
+/// `generate_block_vertices_greedy`, builds for a `BlockList`.
+/// See `mesh_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshStats {
+    /// The number of vertices, opaque and transparent together.
+    pub vertex_count: usize,
+    
+    /// The number of block faces drawn.
+    pub face_count: usize,
+    
+    /// The faces drawn looking each way, indexed by `Face as usize`.
+    pub quads: [usize; 6],
+}
+
+/// Count the faces and vertices of the mesh of `blocks`, with face
+/// masks from `update_face_masks`. The mesh is built by the same
+/// generator the terrain uses, `generate_block_vertices_greedy` if
+/// `greedy` is set and `generate_block_vertices` otherwise, without
+/// any block light. With greedy meshing, a merged quad counts as one
+/// face, and `edge_blending` keeps some faces from being merged.
+pub fn mesh_stats(blocks: &BlockList, adjacent: &AdjacentSectors, draw_frontier: bool,
+                  greedy: bool, edge_blending: bool, atlases: &[AtlasLayout]) -> MeshStats {
+    let mut masks = Vec::new();
+    update_face_masks(&mut masks, &[], blocks, adjacent, draw_frontier);
+    
+    let generate = if greedy { generate_block_vertices_greedy } else { generate_block_vertices };
+    let (batches, transparent) = generate(blocks, &masks, adjacent, edge_blending, None, atlases);
+    
+    let mut stats = MeshStats::default();
+    
+    // Every face is the same number of vertices, all with its face number.
+    let vertices = batches.iter().flat_map(|b| b.iter()).chain(&transparent);
+    for v in vertices.step_by(VERTICES_PER_FACE) {
+        stats.quads[v.2 as usize] += 1;
+    }
+    
+    stats.face_count = stats.quads.iter().sum();
+    stats.vertex_count = batches.iter().map(|b| b.len()).sum::<usize>() + transparent.len();
+    
+    stats
+}
+
+/// Generate the mesh for a `BlockList`, drawing the faces
+/// set in `masks`. See `update_face_masks`.
+///
+/// Returns the opaque vertices, one list per terrain atlas in
+/// `atlases`, then the vertices of transparent blocks, which are
+/// kept apart so they can be sorted and drawn last. Texture
+/// coordinates use the size of each block's own atlas.
+///
+/// Each face is tinted by the `light` in the block it faces, if any,
+/// and by that block's sky light. Its corners are darkened by
+/// ambient occlusion.
+///
+/// If `edge_blending` is set, a ground block next to a different ground
+/// block in the +X direction fades into the neighbor's tile across its
+/// width, so the boundary between materials is not a hard edge.
+pub fn generate_block_vertices(blocks: &BlockList, masks: &[u8], adjacent: &AdjacentSectors,
+                               edge_blending: bool, light: Option<&LightMap>,
+                               atlases: &[AtlasLayout]) -> (Vec<Vec<Vertex>>, Vec<Vertex>) {
     use self::Face::*;
     
-    let mut v = Vec::with_capacity(SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE * 24);
+    let mut batches = vec![Vec::new(); atlases.len()];
+    batches[0].reserve(SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE * 24);
+    let mut transparent = Vec::new();
     
-    for i in blocks {
-        if !i.1.is_air() {
-            if should_create_face(Back, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Back, texture_info);
-            }
-            
-            if should_create_face(Front, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Front, texture_info);
-            }
+    for i in blocks.solid_blocks() {
+        let mask = masks[BlockList::index(i.0)];
+        if mask != 0 {
+            let atlas = i.1.atlas();
+            let out = if i.1.is_transparent() { &mut transparent } else { &mut batches[atlas] };
             
-            if should_create_face(Top, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Top, texture_info);
-            }
-            
-            if should_create_face(Bottom, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Bottom, texture_info);
+            for &face in &[Back, Front, Top, Bottom, Left, Right] {
+                if mask & (1 << face as u8) != 0 {
+                    let blend = if edge_blending {
+                        blend_tile(i, face, blocks, adjacent, &atlases[atlas])
+                    } else {
+                        0
+                    };
+                    let face_light = light.map_or([0.; 3], |l| l.get_f32(facing(i.0, face)));
+                    let sky = face_sky_light(i.0, face, blocks, adjacent);
+                    let ao = face_occlusion(i.0, face, blocks, adjacent);
+                    generate_face(out, (i.0, i.1, blend), face, (face_light, sky), ao, [1.; 3],
+                                  &atlases[atlas]);
+                }
             }
+        }
+    }
+    
+    //generate_face(&mut v);
+    
+    //println!("done!");
+    
+    (batches, transparent)
+}
+
+/// Generate the mesh like `generate_block_vertices`, but merge
+/// neighboring faces that share a block, lighting and direction into
+/// larger quads, one slice of the sector at a time. The output has
+/// the same layout, with far fewer vertices for flat terrain.
+///
+/// Merged faces repeat their tile across the span. Edge blended
+/// faces are never merged, since their blend runs across one block,
+/// and neither are faces whose corners are not equally occluded.
+pub fn generate_block_vertices_greedy(blocks: &BlockList, masks: &[u8],
+                                      adjacent: &AdjacentSectors, edge_blending: bool,
+                                      light: Option<&LightMap>,
+                                      atlases: &[AtlasLayout]) -> (Vec<Vec<Vertex>>, Vec<Vertex>) {
+    use self::Face::*;
+    
+    let mut batches = vec![Vec::new(); atlases.len()];
+    let mut transparent = Vec::new();
+    
+    // The faces of the current slice that still need a quad.
+    let mut slice: Vec<Option<(Block, u32, ([f32; 3], f32), [f32; 4])>> =
+        vec![None; SECTOR_SIZE * SECTOR_SIZE];
+    
+    for &face in &[Back, Front, Top, Bottom, Left, Right] {
+        let (n, a, b) = face_axes(face);
+        let coord_at = |s: usize, i: usize, j: usize| {
+            let mut c = [0; 3];
+            c[n] = s as u8;
+            c[a] = i as u8;
+            c[b] = j as u8;
             
-            if should_create_face(Left, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Left, texture_info);
+            SectorSpaceCoords::new(c[0], c[1], c[2])
+        };
+        
+        for s in 0..SECTOR_SIZE {
+            for j in 0..SECTOR_SIZE {
+                for i in 0..SECTOR_SIZE {
+                    let coord = coord_at(s, i, j);
+                    
+                    slice[i + j * SECTOR_SIZE] = if masks[BlockList::index(coord)] & (1 << face as u8) != 0 {
+                        let block = blocks.get(coord);
+                        let blend = if edge_blending {
+                            blend_tile((coord, block), face, blocks, adjacent, &atlases[block.atlas()])
+                        } else {
+                            0
+                        };
+                        let face_light = light.map_or([0.; 3], |l| l.get_f32(facing(coord, face)));
+                        let sky = face_sky_light(coord, face, blocks, adjacent);
+                        let ao = face_occlusion(coord, face, blocks, adjacent);
+                        
+                        Some((*block, blend, (face_light, sky), ao))
+                    } else {
+                        None
+                    };
+                }
             }
             
-            if should_create_face(Right, i.0, blocks, adjacent) {
-                generate_face(&mut v, i, Right, texture_info);
+            for j in 0..SECTOR_SIZE {
+                let mut i = 0;
+                while i < SECTOR_SIZE {
+                    let key = match slice[i + j * SECTOR_SIZE] {
+                        Some(k) => k,
+                        None => {
+                            i += 1;
+                            continue;
+                        },
+                    };
+                    
+                    // Grow along `a`, then along `b` while every row matches.
+                    let (mut w, mut h) = (1, 1);
+                    let ao = key.3;
+                    if key.1 == 0 && ao.iter().all(|&c| c == ao[0]) {
+                        while i + w < SECTOR_SIZE && slice[i + w + j * SECTOR_SIZE] == Some(key) {
+                            w += 1;
+                        }
+                        
+                        while j + h < SECTOR_SIZE &&
+                              (i..i + w).all(|x| slice[x + (j + h) * SECTOR_SIZE] == Some(key)) {
+                            h += 1;
+                        }
+                    }
+                    
+                    for y in j..j + h {
+                        for x in i..i + w {
+                            slice[x + y * SECTOR_SIZE] = None;
+                        }
+                    }
+                    
+                    let mut extent = [1.; 3];
+                    extent[a] = w as f32;
+                    extent[b] = h as f32;
+                    
+                    let atlas = key.0.atlas();
+                    let out = if key.0.is_transparent() { &mut transparent } else { &mut batches[atlas] };
+                    generate_face(out, (coord_at(s, i, j), &key.0, key.1), face, key.2,
+                                  ao, extent, &atlases[atlas]);
+                    
+                    i += w;
+                }
             }
         }
     }
     
-    //generate_face(&mut v);
+    (batches, transparent)
+}
+
+// The axis a face looks along, then the two axes it spans.
+fn face_axes(face: Face) -> (usize, usize, usize) {
+    use self::Face::*;
     
-    //println!("done!");
+    match face {
+        Back | Front => (2, 0, 1),
+        Top | Bottom => (1, 0, 2),
+        Left | Right => (0, 2, 1),
+    }
+}
+
+/// Get the indices that draw every edge of a triangle list of
+/// `vertex_count` vertices as a line list, for wireframes.
+pub fn triangle_edges(vertex_count: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(vertex_count * 2);
+    
+    for t in 0..vertex_count as u32 / 3 {
+        let (a, b, c) = (t * 3, t * 3 + 1, t * 3 + 2);
+        indices.extend_from_slice(&[a, b, b, c, c, a]);
+    }
+    
+    indices
+}
+
+/// Reorder the quads of a mesh from `generate_block_vertices` so that
+/// the farthest from `eye` come first, for back to front blending.
+/// Each quad is placed by its center.
+pub fn sort_quads(vertices: &[Vertex], eye: Position) -> Vec<Vertex> {
+    let mut quads: Vec<(f32, &[Vertex])> = vertices.chunks(6).map(|q| {
+        // The first and third vertices are opposite corners.
+        let d = [(q[0].0[0] + q[2].0[0]) * 0.5 - eye[0],
+                 (q[0].0[1] + q[2].0[1]) * 0.5 - eye[1],
+                 (q[0].0[2] + q[2].0[2]) * 0.5 - eye[2]];
+        
+        (d[0] * d[0] + d[1] * d[1] + d[2] * d[2], q)
+    }).collect();
+    
+    quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    
+    quads.iter().flat_map(|&(_, q)| q.iter().cloned()).collect()
+}
+
+// The block a face looks out into, or the block itself
+// on the sector boundary.
+fn facing(coord: SectorSpaceCoords, face: Face) -> SectorSpaceCoords {
+    use self::Face::*;
+    
+    let next = match face {
+        Back => coord.back(),
+        Front => coord.front(),
+        Top => coord.top(),
+        Bottom => coord.bottom(),
+        Left => coord.left(),
+        Right => coord.right(),
+    };
     
-    v
+    next.unwrap_or(coord)
 }
 
-fn should_create_face(face: Face, coord: SectorSpaceCoords,
-                      blocks: &BlockList, adjacent: &AdjacentSectors) -> bool {
+// Find the visible faces of one block. See `update_face_masks`.
+fn face_mask(block: (SectorSpaceCoords, &Block), blocks: &BlockList,
+             adjacent: &AdjacentSectors, draw_frontier: bool) -> u8 {
     use self::Face::*;
     
+    if block.1.is_air() {
+        return 0;
+    }
+    
+    let mut mask = 0;
+    for &face in &[Back, Front, Top, Bottom, Left, Right] {
+        if should_create_face(face, block, blocks, adjacent, draw_frontier) {
+            mask |= 1 << face as u8;
+        }
+    }
+    
+    mask
+}
+
+fn should_create_face(face: Face, block: (SectorSpaceCoords, &Block), blocks: &BlockList,
+                      adjacent: &AdjacentSectors, draw_frontier: bool) -> bool {
+    use self::Face::*;
+    
+    let coord = block.0;
+    
     let (block_list, other_coord) = match face {
         /*
             if let Some(c) = coord.back() {
@@ -171,95 +451,354 @@ fn should_create_face(face: Face, coord: SectorSpaceCoords,
         
         Back =>
             coord.back().map_or_else(|| {
-                (adjacent.back.blocks(), Some(SectorSpaceCoords::new(coord.x(), coord.y(), SECTOR_SIZE as u8 - 1)))
-            }, |c| (blocks, Some(c))),
+                (adjacent.back.map(|s| s.blocks()), SectorSpaceCoords::new(coord.x(), coord.y(), SECTOR_SIZE as u8 - 1))
+            }, |c| (Some(blocks), c)),
         Front => 
             coord.front().map_or_else(|| {
-                (adjacent.front.blocks(), Some(SectorSpaceCoords::new(coord.x(), coord.y(), 0)))
-            }, |c| (blocks, Some(c))),
+                (adjacent.front.map(|s| s.blocks()), SectorSpaceCoords::new(coord.x(), coord.y(), 0))
+            }, |c| (Some(blocks), c)),
         Top =>
             coord.top().map_or_else(|| {
-                (adjacent.top.blocks(), Some(SectorSpaceCoords::new(coord.x(), 0, coord.z())))
-            }, |c| (blocks, Some(c))),
+                (adjacent.top.map(|s| s.blocks()), SectorSpaceCoords::new(coord.x(), 0, coord.z()))
+            }, |c| (Some(blocks), c)),
         Bottom =>
             coord.bottom().map_or_else(|| {
-                (adjacent.bottom.blocks(), Some(SectorSpaceCoords::new(coord.x(), SECTOR_SIZE as u8 - 1, coord.z())))
-            }, |c| (blocks, Some(c))),
+                (adjacent.bottom.map(|s| s.blocks()), SectorSpaceCoords::new(coord.x(), SECTOR_SIZE as u8 - 1, coord.z()))
+            }, |c| (Some(blocks), c)),
         Left =>
             coord.left().map_or_else(|| {
-                (adjacent.left.blocks(), Some(SectorSpaceCoords::new(SECTOR_SIZE as u8 - 1, coord.y(), coord.z())))
-            }, |c| (blocks, Some(c))),
+                (adjacent.left.map(|s| s.blocks()), SectorSpaceCoords::new(SECTOR_SIZE as u8 - 1, coord.y(), coord.z()))
+            }, |c| (Some(blocks), c)),
         Right =>
             coord.right().map_or_else(|| {
-                (adjacent.right.blocks(), Some(SectorSpaceCoords::new(0, coord.y(), coord.z())))
-            }, |c| (blocks, Some(c))),
+                (adjacent.right.map(|s| s.blocks()), SectorSpaceCoords::new(0, coord.y(), coord.z()))
+            }, |c| (Some(blocks), c)),
     };
     
-    other_coord.map_or(true, |c| !block_list.get(c).needs_rendering())
+    block_list.map_or(draw_frontier, |l| !l.get(other_coord).hides(block.1))
 }
 
-fn generate_face(v: &mut Vec<Vertex>, block: (SectorSpaceCoords, &Block),
-                 face: Face, texture_info: &OutputInfo) {
+// Find the tile to blend toward for a face of a block, or `0` for
+// none. See `BlendTile`. Only blocks sharing an atlas blend together.
+fn blend_tile(block: (SectorSpaceCoords, &Block), face: Face, blocks: &BlockList,
+              adjacent: &AdjacentSectors, layout: &AtlasLayout) -> u32 {
+    let coord = block.0;
+    
+    if !block.1.blends() {
+        return 0;
+    }
+    
+    let neighbor = match coord.right() {
+        Some(c) => Some(blocks.get(c)),
+        None => adjacent.right.map(|s| s.blocks().get(SectorSpaceCoords::new(0, coord.y(), coord.z()))),
+    };
+    
+    match neighbor {
+        Some(n) if n.blends() && n.atlas() == block.1.atlas() &&
+                   n != block.1 => layout.index(layout.tile(n, face)) + 1,
+        _ => 0,
+    }
+}
+
+// The corners of the unit cube that make up a face, in vertex order.
+fn face_corners(face: Face) -> [usize; 4] {
     use self::Face::*;
     
-    //Bottom => ([2, 5, 6, 1], ([1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0])),
+    //Bottom => [2, 5, 6, 1],
     
-    let uvs = tex_coords(block.1, texture_info);
+    match face {
+        Back => [0, 1, 2, 3],
+        Front => [4, 5, 6, 7],
+        Top => [5, 2, 1, 6],
+        Bottom => [3, 4, 7, 0],
+        Left => [7, 6, 1, 0],
+        Right => [3, 2, 5, 4],
+    }
+}
+
+// Find the ambient occlusion of each corner of a face, in vertex
+// order. A corner is darkened by the opaque blocks beside it and
+// diagonal to it, just outside the face. If both sides are solid,
+// the corner is fully enclosed, whatever the diagonal.
+fn face_occlusion(coord: SectorSpaceCoords, face: Face, blocks: &BlockList,
+                  adjacent: &AdjacentSectors) -> [f32; 4] {
+    let (_, a, b) = face_axes(face);
+    let front = front_of(coord, face);
     
-    let (triangles, uv) = match face {
-        Back => ([0, 1, 2, 3], uvs),
-        Front => ([4, 5, 6, 7], uvs),
-        Top => ([5, 2, 1, 6], uvs),
-        Bottom => ([3, 4, 7, 0], uvs),
-        Left => ([7, 6, 1, 0], uvs),
-        Right => ([3, 2, 5, 4], uvs),
+    let solid = |da: i32, db: i32| {
+        let mut p = front;
+        p[a] += da;
+        p[b] += db;
+        
+        block_at(p, blocks, adjacent).map_or(false, |k| k.needs_rendering() && !k.is_transparent())
+    };
+    
+    let mut ao = [1.; 4];
+    for (i, &corner) in face_corners(face).iter().enumerate() {
+        let da = if POSITIONS[corner][a] > 0. { 1 } else { -1 };
+        let db = if POSITIONS[corner][b] > 0. { 1 } else { -1 };
+        
+        let (side1, side2, diagonal) = (solid(da, 0), solid(0, db), solid(da, db));
+        let open = if side1 && side2 {
+            0
+        } else {
+            3 - side1 as u32 - side2 as u32 - diagonal as u32
+        };
+        
+        ao[i] = open as f32 / 3.;
+    }
+    
+    ao
+}
+
+// Find the sky light a face receives, from `0` to `1`. This is the
+// light of the block it looks out into. Faces looking into a missing
+// neighbor are fully lit.
+fn face_sky_light(coord: SectorSpaceCoords, face: Face, blocks: &BlockList,
+                  adjacent: &AdjacentSectors) -> f32 {
+    match neighbor_at(front_of(coord, face), blocks, adjacent) {
+        Some((list, c)) => list.light_at(c) as f32 / MAX_LIGHT as f32,
+        None => 1.,
+    }
+}
+
+// The sector space position of the block a face looks out
+// into, which may be just outside the sector.
+fn front_of(coord: SectorSpaceCoords, face: Face) -> [i32; 3] {
+    let n = face.normal();
+    
+    [coord.x() as i32 + n[0], coord.y() as i32 + n[1], coord.z() as i32 + n[2]]
+}
+
+// Look up a block by sector space position. See `neighbor_at`.
+fn block_at<'a>(p: [i32; 3], blocks: &'a BlockList,
+                adjacent: &AdjacentSectors<'a>) -> Option<&'a Block> {
+    neighbor_at(p, blocks, adjacent).map(|(list, c)| list.get(c))
+}
+
+// Find the `BlockList` and coords of a sector space position, which
+// may be one block outside the sector along a single axis, in a
+// neighboring sector. Positions outside along several axes, or in
+// missing neighbors, give `None`.
+fn neighbor_at<'a>(p: [i32; 3], blocks: &'a BlockList,
+                   adjacent: &AdjacentSectors<'a>) -> Option<(&'a BlockList, SectorSpaceCoords)> {
+    let size = SECTOR_SIZE as i32;
+    let outside: Vec<usize> = (0..3).filter(|&i| p[i] < 0 || p[i] >= size).collect();
+    
+    let list = match outside.len() {
+        0 => Some(blocks),
+        1 => {
+            let i = outside[0];
+            let sector = match (i, p[i] < 0) {
+                (0, true) => adjacent.left,
+                (0, false) => adjacent.right,
+                (1, true) => adjacent.bottom,
+                (1, false) => adjacent.top,
+                (_, true) => adjacent.back,
+                (_, false) => adjacent.front,
+            };
+            
+            sector.map(|s| s.blocks())
+        },
+        _ => None,
+    };
+    
+    list.map(|l| (l, SectorSpaceCoords::new(p[0].rem_euclid(size) as u8,
+                                            p[1].rem_euclid(size) as u8,
+                                            p[2].rem_euclid(size) as u8)))
+}
+
+// Add one face to the mesh. `light` is the colored block light and
+// the sky light, see `face_sky_light`. `ao` is the ambient occlusion
+// of each corner, see `face_occlusion`. `extent` is the size of the
+// face's box in blocks, which is `[1.; 3]` unless faces were merged.
+fn generate_face(v: &mut Vec<Vertex>, block: (SectorSpaceCoords, &Block, u32),
+                 face: Face, light: ([f32; 3], f32), ao: [f32; 4], extent: Position,
+                 layout: &AtlasLayout) {
+    let triangles = face_corners(face);
+    let uv = tex_coords(block.1, face, layout);
+    
+    // Extend the far edges of the tile across a merged face, so
+    // the texture repeats rather than stretches. The fragment shader
+    // wraps the coordinates back into the tile. The third corner keeps
+    // the tile's top left, since it is the provoking vertex.
+    let span = (extent[axis_between(triangles[1], triangles[2])],
+                extent[axis_between(triangles[2], triangles[3])]);
+    let uv = if span == (1., 1.) {
+        uv
+    } else {
+        let (u0, v0) = ((uv.2)[0], (uv.2)[1]);
+        let (du, dv) = ((uv.0)[0] - u0, (uv.0)[1] - v0);
+        
+        ([u0 + du * span.0, v0 + dv * span.1],
+         [u0 + du * span.0, v0],
+         uv.2,
+         [u0, v0 + dv * span.1])
     };
     
     let original = ((block.0).x() as f32, (block.0).y() as f32, (block.0).z() as f32);
+    let id = block.1.id() as u32;
+    let anim = block.1.animation().map_or(0, |a| a.pack());
+    let blend = block.2;
+    
+    // The blend runs across X, reaching the neighbor's tile at the +X side.
+    let weight = |corner: usize| if blend == 0 { 0. } else { POSITIONS[corner][0] };
+    
+    let corner = |corner: usize| {
+        let p = POSITIONS[corner];
+        [p[0] * extent[0], p[1] * extent[1], p[2] * extent[2]]
+    };
     
-    let mut vtx0 = (POSITIONS[triangles[0]], uv.0, face as u32);
+    let mut vtx0 = (corner(triangles[0]), uv.0, face as u32, id, anim,
+                    blend, weight(triangles[0]), light.0, light.1, ao[0]);
     vtx0.0[0] += original.0;
     vtx0.0[1] += original.1;
     vtx0.0[2] += original.2;
     
-    let mut vtx1 = (POSITIONS[triangles[1]], uv.1, face as u32);
+    let mut vtx1 = (corner(triangles[1]), uv.1, face as u32, id, anim,
+                    blend, weight(triangles[1]), light.0, light.1, ao[1]);
     vtx1.0[0] += original.0;
     vtx1.0[1] += original.1;
     vtx1.0[2] += original.2;
     
-    let mut vtx2 = (POSITIONS[triangles[2]], uv.2, face as u32);
+    let mut vtx2 = (corner(triangles[2]), uv.2, face as u32, id, anim,
+                    blend, weight(triangles[2]), light.0, light.1, ao[2]);
     vtx2.0[0] += original.0;
     vtx2.0[1] += original.1;
     vtx2.0[2] += original.2;
     
-    let mut vtx3 = (POSITIONS[triangles[3]], uv.3, face as u32);
+    let mut vtx3 = (corner(triangles[3]), uv.3, face as u32, id, anim,
+                    blend, weight(triangles[3]), light.0, light.1, ao[3]);
     vtx3.0[0] += original.0;
     vtx3.0[1] += original.1;
     vtx3.0[2] += original.2;
     
-    // Add to mesh
+    // Add to mesh. Both triangles end on the third corner, so
+    // it is the provoking vertex of the whole face.
     v.push(vtx0);
     v.push(vtx1);
     v.push(vtx2);
     
+    v.push(vtx3);
     v.push(vtx0);
     v.push(vtx2);
-    v.push(vtx3);
 }
 
-fn tex_coords(block: &Block, texture_info: &OutputInfo) -> (UV, UV, UV, UV) {
-    let (width, height) = (texture_info.width as f32,
-                           texture_info.height as f32);
-    
-    let (ru, rv) = (TILE_SIZE / width,
-                    TILE_SIZE / height);
+// The axis along which two corners of the unit cube differ.
+fn axis_between(a: usize, b: usize) -> usize {
+    (0..3).find(|&i| POSITIONS[a][i] != POSITIONS[b][i]).unwrap_or(0)
+}
+
+/// Get the corners of the atlas tile for a face of a block.
+/// See `AtlasLayout::tile`.
+pub fn tex_coords(block: &Block, face: Face, layout: &AtlasLayout) -> (UV, UV, UV, UV) {
+    let (ru, rv) = (1. / layout.columns() as f32,
+                    1. / layout.rows() as f32);
     
-    let num = *block as u32 as f32 - 1.;
+    let (col, row) = layout.tile(block, face);
+    let (col, row) = (col as f32, row as f32);
     
     (
-        [ru * (num + 1.), rv],
-        [ru * (num + 1.), 0.],
-        [ru *  num,       0.],
-        [ru *  num,       rv],
+        [ru * (col + 1.), rv * (row + 1.)],
+        [ru * (col + 1.), rv *  row      ],
+        [ru *  col,       rv *  row      ],
+        [ru *  col,       rv * (row + 1.)],
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use super::super::GenerationCause;
+    use super::super::voxel::Sector;
+    use super::super::world_gen::WorldGen;
+    
+    const FACES: [Face; 6] = [Face::Back, Face::Front, Face::Top,
+                              Face::Bottom, Face::Left, Face::Right];
+    
+    #[test]
+    fn face_normals_are_distinct_unit_axes() {
+        let normals: HashSet<_> = FACES.iter().map(|f| f.normal()).collect();
+        assert_eq!(normals.len(), 6);
+        
+        for n in &normals {
+            assert_eq!(n.iter().map(|c| c.abs()).sum::<i32>(), 1);
+        }
+        
+        // The normals agree with the neighbors of `SectorSpaceCoords`.
+        let middle = SectorSpaceCoords::new(5, 5, 5);
+        for f in &FACES {
+            let next = facing(middle, *f);
+            let n = f.normal();
+            
+            assert_eq!([next.x() as i32 - 5, next.y() as i32 - 5, next.z() as i32 - 5], n);
+        }
+    }
+    
+    // A layout for the single terrain atlas.
+    fn atlases() -> Vec<AtlasLayout> {
+        vec![AtlasLayout::new(256, 256)]
+    }
+    
+    // A sector filled with `block`.
+    fn filled(block: Block) -> Sector {
+        Sector::new(BlockList::new([block; SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE]),
+                    GenerationCause::Streaming)
+    }
+    
+    #[test]
+    fn boundary_face_hidden_by_solid_neighbor_sector() {
+        let mut blocks = BlockList::new_air();
+        blocks.set(SectorSpaceCoords::new(0, 5, 5), Block::Limestone);
+        
+        let (solid, air) = (filled(Block::Limestone), filled(Block::Air));
+        let left = |s| AdjacentSectors::new(None, None, None, None, s, None);
+        
+        let hidden = mesh_stats(&blocks, &left(Some(&solid)), true, false, false, &atlases());
+        assert_eq!(hidden.quads[Face::Left as usize], 0);
+        assert_eq!(hidden.face_count, 5);
+        
+        let open = mesh_stats(&blocks, &left(Some(&air)), true, false, false, &atlases());
+        assert_eq!(open.quads[Face::Left as usize], 1);
+    }
+    
+    // The number of vertices in a generated mesh.
+    fn vertex_len(mesh: &(Vec<Vec<Vertex>>, Vec<Vertex>)) -> usize {
+        mesh.0.iter().map(|b| b.len()).sum::<usize>() + mesh.1.len()
+    }
+    
+    #[test]
+    fn mesh_stats_match_generated_vertices() {
+        let blocks = WorldGen::new().generate((0, 0, 0));
+        let adjacent = AdjacentSectors::new(None, None, None, None, None, None);
+        
+        let mut masks = Vec::new();
+        update_face_masks(&mut masks, &[], &blocks, &adjacent, true);
+        
+        let naive = generate_block_vertices(&blocks, &masks, &adjacent, false, None, &atlases());
+        let stats = mesh_stats(&blocks, &adjacent, true, false, false, &atlases());
+        assert!(stats.vertex_count > 0);
+        assert_eq!(stats.vertex_count, vertex_len(&naive));
+        assert_eq!(stats.vertex_count, stats.face_count * VERTICES_PER_FACE);
+        
+        let greedy = generate_block_vertices_greedy(&blocks, &masks, &adjacent, false, None, &atlases());
+        let greedy_stats = mesh_stats(&blocks, &adjacent, true, true, false, &atlases());
+        assert_eq!(greedy_stats.vertex_count, vertex_len(&greedy));
+        assert!(greedy_stats.vertex_count < stats.vertex_count);
+    }
+    
+    #[test]
+    fn greedy_mesh_stats_merge_flat_faces() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((0, 0, 0), (31, 0, 31), Block::Limestone);
+        let adjacent = AdjacentSectors::new(None, None, None, None, None, None);
+        
+        let naive = mesh_stats(&blocks, &adjacent, true, false, false, &atlases());
+        assert_eq!(naive.quads[Face::Top as usize], SECTOR_SIZE * SECTOR_SIZE);
+        
+        let greedy = mesh_stats(&blocks, &adjacent, true, true, false, &atlases());
+        assert_eq!(greedy.quads, [1; 6]);
+        assert_eq!(greedy.vertex_count, 6 * VERTICES_PER_FACE);
+    }
+}
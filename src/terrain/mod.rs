@@ -1,29 +1,46 @@
 //! Module related to managing, drawing, and colliding with terrain.
 
+mod debug;
+mod light;
 mod mesh_gen;
+mod particles;
+mod region;
 mod voxel;
 mod world_gen;
 
-use std::collections::{HashMap, VecDeque};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
 use std::mem;
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
-use luminance::framebuffer::Framebuffer;
+use luminance::blending::{Equation, Factor};
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
 use luminance::linear::M44;
-use luminance::pipeline::{entry, pipeline, RenderState};
+use luminance::pipeline::{entry, pipeline, Gpu, RenderState, ShadingGate};
+use luminance::tess::{Mode, Tess, TessVertices};
 use luminance::texture::{Dim2, Flat};
 use luminance::shader::program::{Program, ProgramError, Uniform, UniformBuilder,
-                                 UniformInterface, UniformWarning};
-use luminance_glfw::{Device, GLFWDevice};
+                                 UniformInterface, UniformWarning, Uniformable};
 use camera::Camera;
-use maths::{Frustum, ToMatrix, Translation};
+use maths::{self, Frustum, ToMatrix, Translation, Vec3};
 use model::Drawable;
 use resources::Resources;
-use shader;
-use self::voxel::{AdjacentSectors, Block, BlockList, Sector, SectorSpaceCoords};
-use self::world_gen::WorldGen;
+use shader::{ShaderError, ShaderSource};
+use self::particles::Particles;
+use self::voxel::Sector;
+pub use self::debug::DebugTriangle;
+pub use self::light::{LightColor, MAX_LIGHT};
+pub use self::mesh_gen::{mesh_stats, Face, MeshStats};
+pub use self::voxel::{AdjacentSectors, Animation, Block, BlockList, DeserializeError,
+                      SectorSpaceCoords};
+pub use self::world_gen::{generate_region, hash_coords, rng_at, Biome, OreConfig,
+                          WorldGen, DEFAULT_CAVE_FREQUENCY, DEFAULT_CAVE_THRESHOLD,
+                          DEFAULT_ORES, DEFAULT_SEA_LEVEL};
 
 // Type of terrain position vertex attribute.
 type Position = [f32; 3];
@@ -36,32 +53,168 @@ type UV = [f32; 2];
 // an axis.
 type FaceNum = u32;
 
+// Type of block id attribute, so shaders can
+// apply per-block effects. Air (`0`) has none.
+type BlockId = u32;
+
+// Type of texture animation attribute. See `Animation::pack`.
+type AnimInfo = u32;
+
+// Type of the edge blending tile attribute. This is the atlas
+// tile index plus one of the neighbor to blend toward, or `0`.
+type BlendTile = u32;
+
+// Type of the edge blending weight attribute, from `0` (this
+// block's tile) to `1` (the neighbor's tile).
+type BlendWeight = f32;
+
+// Type of the colored block light attribute, with
+// channels from `0` to `1`.
+type Light = [f32; 3];
+
+// Type of the sky light attribute, from `0` to `1`.
+type SkyLight = f32;
+
+// Type of the ambient occlusion attribute, from `0`
+// (a fully enclosed corner) to `1` (an open one).
+type Occlusion = f32;
+
 // A terrain vertex.
-type Vertex = (Position, UV, FaceNum);
+type Vertex = (Position, UV, FaceNum, BlockId, AnimInfo, BlendTile, BlendWeight,
+               Light, SkyLight, Occlusion);
 
 /// The length of one side of a cubic sector.
 pub const SECTOR_SIZE: usize = 32;
 
-const CLEAR_COLOR: [f32; 4] = [0.2, 0.75, 0.8, 1.0];
+/// The sky color that terrain is drawn over.
+pub const CLEAR_COLOR: [f32; 4] = [0.2, 0.75, 0.8, 1.0];
 const COLLIDE_PADDING: f32 = 0.3;
 
+// The opacity of transparent blocks, such as water.
+const TRANSPARENT_OPACITY: f32 = 0.7;
+
+// Slack allowed when comparing a step's rise to the step height.
+const STEP_EPSILON: f32 = 1e-3;
+
+/// The default height that `Terrain::collide` will step up onto
+/// rather than stopping against.
+pub const DEFAULT_STEP_HEIGHT: f32 = 1.;
+
+/// The number of sectors the camera may travel from the render origin,
+/// along any axis, before the world is rebased around the camera.
+/// At 16 sectors, rendered positions never exceed roughly 512 units,
+/// where `f32` is still precise to well under a thousandth of a block.
+pub const REBASE_THRESHOLD: i32 = 16;
+
+// The most sectors remeshed due to neighbor changes
+// or block edits per update.
+const MAX_REMESH_PER_UPDATE: usize = 4;
+
+// The time, in seconds, that `update` may spend loading
+// and remeshing sectors before leaving the rest for later.
+const MAX_LAG: f64 = 0.05;
+
+/// The default horizontal radius, in sectors, that is meshed and drawn.
+pub const DEFAULT_RENDER_RADIUS: i32 = 2;
+
+/// The default horizontal radius, in sectors, that is generated.
+pub const DEFAULT_GENERATE_RADIUS: i32 = 3;
+
+/// The default direction toward the sun, which lights top faces
+/// the most and bottom faces the least.
+pub const DEFAULT_SUN_DIRECTION: [f32; 3] = [0.3, 0.8, 0.5];
+
+/// The default limit on sectors waiting to be generated.
+pub const DEFAULT_MAX_PENDING_REQUESTS: usize = 256;
+
+/// The default number of threads generating sectors.
+pub const DEFAULT_WORKERS: usize = 8;
+
+/// The default time between searches for sectors near
+/// the camera that need to be generated or meshed.
+pub const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_secs(4);
+
 /// Drawable manager for world terrain. Handles the rendering
 /// of each sector.
+///
+/// Each frame is split into three phases:
+/// * `update` streams sectors in and out around the camera.
+/// * `tick` advances the block simulation by a fixed timestep,
+///   and may run zero or more times per frame.
+/// * `draw` renders the loaded sectors.
+///
+/// Rendering uses a floating origin: the camera and all models
+/// live in a local space centered on the sector `origin`, while
+/// sector coordinates stay absolute. See `REBASE_THRESHOLD`.
 pub struct Terrain<'a> {
     shader: Program<Vertex, (), Uniforms>,
+    shader_source: ShaderSource<'a>,
     resources: &'a Resources,
     sectors: HashMap<(i32, i32, i32), Sector>,
+    origin: (i32, i32, i32),
+    ticks: u64,
+    time: f32,
+    frontier_faces: FrontierFaces,
+    remesh: HashSet<(i32, i32, i32)>,
+    camera_sector: Option<(i32, i32, i32)>,
+    crossed_sector: bool,
+    particles: Particles,
+    break_particles: bool,
+    texel_lod_bias: f32,
+    frustum_culling: bool,
+    sort_transparent: bool,
+    greedy_meshing: bool,
+    wireframe: bool,
+    edge_blending: bool,
+    light_colors: HashMap<Block, LightColor>,
+    fog_color: [f32; 3],
+    fog_distance: f32,
+    sun_direction: [f32; 3],
+    render_radius: i32,
+    generate_radius: i32,
+    region_dir: Option<PathBuf>,
+    epoch: u32,
+    block_event_handler: Option<Box<dyn FnMut(BlockEvent)>>,
+    render_stats: Cell<RenderStats>,
+    draw_order: RefCell<Vec<(f32, (i32, i32, i32))>>,
     shared_info: SharedInfo,
     nearby_rx: Receiver<Nearby>,
-    needed_tx: Sender<(i32, i32, i32)>,
+    needed_tx: Sender<Request>,
 }
 
 impl<'a> Terrain<'a> {
-    /// Create a new `Terrain` using the shared `Resources`.
+    /// Create a new `Terrain` using the shared `Resources`
+    /// and the shader pair from `shaders`, with `workers`
+    /// threads generating sectors. At least one is started.
+    /// # Panics
+    /// This constructor panics if shaders fail to load.
+    /// See `try_new`.
+    pub fn new(resources: &'a Resources, shaders: ShaderSource<'a>, workers: usize) -> Terrain<'a> {
+        Self::try_new(resources, shaders, workers).unwrap()
+    }
+    
+    /// Create a new `Terrain` like `new`, but return the error
+    /// if the shaders fail to load instead of panicking.
+    pub fn try_new(resources: &'a Resources, shaders: ShaderSource<'a>,
+                   workers: usize) -> Result<Terrain<'a>, ShaderError> {
+        Self::try_with_generator(resources, shaders, WorldGen::new(), workers)
+    }
+    
+    /// Create a new `Terrain` like `new`, generating sectors with `gen`.
     /// # Panics
     /// This constructor panics if shaders fail to load.
-    pub fn new(resources: &'a Resources) -> Terrain<'a> {
-        let (shader, warnings) = Self::load_shaders().unwrap();
+    /// See `try_with_generator`.
+    pub fn with_generator(resources: &'a Resources, shaders: ShaderSource<'a>,
+                          gen: WorldGen, workers: usize) -> Terrain<'a> {
+        Self::try_with_generator(resources, shaders, gen, workers).unwrap()
+    }
+    
+    /// Create a new `Terrain` like `with_generator`, but return the
+    /// error if the shaders fail to load instead of panicking. No
+    /// worker threads are started unless the shaders load.
+    pub fn try_with_generator(resources: &'a Resources, shaders: ShaderSource<'a>,
+                              gen: WorldGen, workers: usize) -> Result<Terrain<'a>, ShaderError> {
+        let (shader, warnings) = Self::load_shaders(shaders)?;
         for warn in &warnings {
             eprintln!("{:?}", warn);
         }
@@ -88,23 +241,55 @@ impl<'a> Terrain<'a> {
         
         let (nearby_tx, nearby_rx) = mpsc::channel();
         let (needed_tx, needed_rx) = mpsc::channel();
-        TerrainGenThread::new(shared_info.clone(), nearby_tx, needed_rx).spawn();
+        TerrainGenThread::new(shared_info.clone(), gen, workers.max(1), nearby_tx, needed_rx).spawn();
         
-        Terrain {
+        Ok(Terrain {
             resources,
             sectors,
+            origin: (0, 0, 0),
+            ticks: 0,
+            time: 0.,
+            frontier_faces: FrontierFaces::Wait,
+            remesh: HashSet::new(),
+            camera_sector: None,
+            crossed_sector: false,
+            particles: Particles::new(),
+            break_particles: true,
+            texel_lod_bias: 0.,
+            frustum_culling: true,
+            sort_transparent: true,
+            greedy_meshing: false,
+            wireframe: false,
+            edge_blending: false,
+            light_colors: HashMap::new(),
+            fog_color: [CLEAR_COLOR[0], CLEAR_COLOR[1], CLEAR_COLOR[2]],
+            fog_distance: fog_distance_for(DEFAULT_RENDER_RADIUS),
+            sun_direction: DEFAULT_SUN_DIRECTION,
+            render_radius: DEFAULT_RENDER_RADIUS,
+            generate_radius: DEFAULT_GENERATE_RADIUS,
+            region_dir: None,
+            epoch: 0,
+            block_event_handler: None,
+            render_stats: Cell::new(RenderStats::default()),
+            draw_order: RefCell::new(Vec::new()),
             shader,
+            shader_source: shaders,
             shared_info,
             nearby_rx,
             needed_tx,
-        }
+        })
     }
     
     /// Perform a frame update.
     /// May block for some time until a mutex can be aquired.
-    pub fn update(&mut self, camera: &Camera) {
-        let translation = camera.translation().clone();
-        self.shared_info.lock().unwrap().player_pos = translation.clone();
+    /// The camera is moved if the world is rebased.
+    pub fn update(&mut self, camera: &mut Camera) {
+        self.rebase(camera);
+        
+        let sector = self.world_sector_at(camera.translation());
+        self.shared_info.lock().unwrap().player_sector = sector;
+        
+        self.crossed_sector = enter_sector(&mut self.camera_sector, sector);
         
         let begin = Instant::now();
         while let Ok(nearby) = self.nearby_rx.try_recv() {
@@ -118,286 +303,1049 @@ impl<'a> Terrain<'a> {
                             break;
                         }
                         
-                        let model;
                         {
                             let sector = self.sectors.get(&sector_coords).unwrap();
-                            if !sector.blocks().needs_rendering() || sector.model().is_some() {
+                            if !sector.blocks().needs_rendering() {
                                 //println!("bail2");
                                 break;
                             }
                             
                             //println!("sector_coords: {:?}", sector_coords);
                             
-                            let back   = (sector_coords.0,     sector_coords.1,     sector_coords.2 - 1);
-                            let front  = (sector_coords.0,     sector_coords.1,     sector_coords.2 + 1);
-                            let top    = (sector_coords.0,     sector_coords.1 + 1, sector_coords.2    );
-                            let bottom = (sector_coords.0,     sector_coords.1 - 1, sector_coords.2    );
-                            let left   = (sector_coords.0 - 1, sector_coords.1,     sector_coords.2    );
-                            let right  = (sector_coords.0 + 1, sector_coords.1,     sector_coords.2    );
-                            
-                            let back = self.sectors.get(&back);
-                            if back.is_none() {
-                                break;
-                            }
-                            
-                            let front = self.sectors.get(&front);
-                            if front.is_none() {
-                                break;
-                            }
-                            
-                            let top = self.sectors.get(&top);
-                            if top.is_none() {
-                                break;
-                            }
-                            
-                            let bottom = self.sectors.get(&bottom);
-                            if bottom.is_none() {
-                                break;
-                            }
+                            let frontier = !self.adjacent_sectors(sector_coords).is_complete();
                             
-                            let left = self.sectors.get(&left);
-                            if left.is_none() {
+                            // Meshed sectors are only rebuilt once a frontier
+                            // sector finally has all of its neighbors.
+                            if sector.model().is_some() && (!sector.is_frontier() || frontier) {
                                 break;
                             }
                             
-                            let right = self.sectors.get(&right);
-                            if right.is_none() {
+                            if frontier && self.frontier_faces == FrontierFaces::Wait {
                                 break;
                             }
-                            
-                            let adjacent = AdjacentSectors::new(back.unwrap(), front.unwrap(),
-                                                                top.unwrap(), bottom.unwrap(),
-                                                                left.unwrap(), right.unwrap());
-                                
-                            model = sector.create_model(self.resources, sector_coords, &adjacent);
                         }
                         
-                        let sector = self.sectors.get_mut(&sector_coords).unwrap();
-                        sector.set_model(model);
+                        self.remesh_sector(sector_coords);
                     } else {
-                        self.needed_tx.send(sector_coords).unwrap();
+                        self.needed_tx.send((sector_coords, GenerationCause::Streaming)).unwrap();
                     }
                 },
-                Nearby::Generated(sector_coords, block_list) => {
-                    self.sectors.entry(sector_coords).or_insert_with(|| Sector::new(block_list));
+                Nearby::Generated(sector_coords, block_list, cause, epoch) => {
+                    if epoch == self.epoch && !self.sectors.contains_key(&sector_coords) {
+                        self.sectors.insert(sector_coords, Sector::new(block_list, cause));
+                        self.mark_neighbors_for_remesh(sector_coords);
+                    }
                 },
             }
             //println!("nearby: {:?}", sector);
             
-            let duration = Instant::now() - begin;
-            
-            let seconds = duration.as_secs() as f64 +
-                          duration.subsec_nanos() as f64 * 1e-9;
-            
-            if seconds > 0.05 {
+            if seconds_since(begin) > MAX_LAG {
                 //println!("too long: {}", seconds);
                 break;
             }
         }
         //println!("time: {:?}", Instant::now() - begin);
         
-        let sector = sector_at(&translation);
-        self.sectors.retain(|&k, _| {
-            let dx = k.0 as f32 - sector.0 as f32;
-            let dy = k.1 as f32 - sector.1 as f32;
-            let dz = k.2 as f32 - sector.2 as f32;
-            
-            let dist_sq = dx * dx + dy * dy + dz * dz;
-            
-            //println!("{}", dist_sq);
-            
-            dist_sq < 280.
+        self.rebuild_dirty(begin);
+        
+        let (render_radius, generate_radius) = (self.render_radius, self.generate_radius);
+        self.sectors.retain(|&k, s| {
+            match retain_sector(k, sector, render_radius, generate_radius) {
+                Some(true) => true,
+                Some(false) => {
+                    s.set_model(None);
+                    true
+                },
+                None => false,
+            }
         });
+        
+        for sector in self.sectors.values_mut() {
+            sector.update_transparent(camera.translation(), self.sort_transparent);
+        }
     }
     
-    /// Adjust for collisions with the terrain.
-    pub fn collide(&self, translation: &mut Translation) {
-        {
-            let back_t = Translation::new(translation.x, translation.y, translation.z.round() - 1.);
-            let back = match self.get_visible_block(&back_t) {
-                Some(b) => !b.is_air(),
-                None => false,
-            };
-            
-            let margin = back_t.z + 1. + COLLIDE_PADDING;
-            if back && translation.z < margin {
-                translation.z = margin;
-            }
+    /// Set how far from the camera sectors are drawn and generated,
+    /// in sectors along each horizontal axis. The generation radius
+    /// is raised to at least the render radius.
+    ///
+    /// Generating past the render radius means sectors are ready,
+    /// and available as neighbors for meshing, before they come into
+    /// view, which reduces pop in and seams at the edge. The cost is
+    /// memory: every generated sector keeps its blocks, about 32KiB
+    /// each, so the block memory grows with the square of the radius.
+    ///
+    /// The fog distance is reset to match the new render radius.
+    pub fn set_view_radii(&mut self, render_radius: i32, generate_radius: i32) {
+        self.render_radius = render_radius.max(0);
+        self.generate_radius = generate_radius.max(self.render_radius);
+        self.fog_distance = fog_distance_for(self.render_radius);
+        
+        let mut info = self.shared_info.lock().unwrap();
+        info.render_radius = self.render_radius;
+        info.generate_radius = self.generate_radius;
+    }
+    
+    /// Limit how many sectors may wait to be generated. Once the limit
+    /// is reached, requests for nearer sectors evict the farthest
+    /// pending ones, so that fast movement does not leave the queue
+    /// full of sectors that are no longer near the camera.
+    pub fn set_max_pending_requests(&mut self, max: usize) {
+        self.shared_info.lock().unwrap().max_pending = max.max(1);
+    }
+    
+    /// Set the time between searches for sectors near the camera that
+    /// need to be generated or meshed. Shorter intervals notice camera
+    /// movement sooner, but the search itself is not free.
+    pub fn set_scan_interval(&mut self, interval: Duration) {
+        self.shared_info.lock().unwrap().scan_interval = interval;
+    }
+    
+    /// Get the time between searches for sectors near the camera.
+    pub fn scan_interval(&self) -> Duration {
+        self.shared_info.lock().unwrap().scan_interval
+    }
+    
+    /// Get the horizontal radius, in sectors, that is drawn.
+    pub fn render_radius(&self) -> i32 {
+        self.render_radius
+    }
+    
+    /// Get the horizontal radius, in sectors, that is generated.
+    pub fn generate_radius(&self) -> i32 {
+        self.generate_radius
+    }
+    
+    /// Set the distance, in blocks, at which terrain fully fades into
+    /// the fog color. By default this is the edge of the render radius,
+    /// so sectors fade in rather than popping in. `0` disables fog.
+    pub fn set_fog_distance(&mut self, distance: f32) {
+        self.fog_distance = distance.max(0.);
+    }
+    
+    /// Get the distance, in blocks, at which terrain is fully fogged.
+    pub fn fog_distance(&self) -> f32 {
+        self.fog_distance
+    }
+    
+    /// Set the color that distant terrain fades into.
+    /// The default is the RGB of `CLEAR_COLOR`.
+    pub fn set_fog_color(&mut self, color: [f32; 3]) {
+        self.fog_color = color;
+    }
+    
+    /// Get the color that distant terrain fades into.
+    pub fn fog_color(&self) -> [f32; 3] {
+        self.fog_color
+    }
+    
+    /// Set the direction pointing toward the sun, which faces are
+    /// shaded against. It does not need to be normalized, but must
+    /// not be zero. Faces turned away from the sun are not black,
+    /// but get only the ambient part of the light.
+    pub fn set_sun_direction(&mut self, direction: [f32; 3]) {
+        self.sun_direction = direction;
+    }
+    
+    /// Get the direction pointing toward the sun.
+    pub fn sun_direction(&self) -> [f32; 3] {
+        self.sun_direction
+    }
+    
+    /// Unload every sector and drop pending generation requests, so
+    /// that the world is generated again from scratch by later calls
+    /// to `update`. Sectors still being generated are discarded when
+    /// they arrive.
+    pub fn clear(&mut self) {
+        self.sectors.clear();
+        self.remesh.clear();
+        
+        while self.nearby_rx.try_recv().is_ok() {}
+        
+        self.epoch = self.epoch.wrapping_add(1);
+        self.shared_info.lock().unwrap().epoch = self.epoch;
+    }
+    
+    /// Set the region directory that sectors are saved to by
+    /// `save_sector`. Sectors found there are loaded instead of
+    /// generated. `None` (the default) generates every sector.
+    pub fn set_region_dir(&mut self, dir: Option<PathBuf>) {
+        self.region_dir = dir.clone();
+        self.shared_info.lock().unwrap().region_dir = dir;
+    }
+    
+    /// Get the region directory, if one is set.
+    pub fn region_dir(&self) -> Option<&PathBuf> {
+        self.region_dir.as_ref()
+    }
+    
+    /// Save the sector at these sector coordinates to the region
+    /// directory. Returns `false` if there is no region directory,
+    /// or the sector is not loaded.
+    pub fn save_sector(&self, pos: (i32, i32, i32)) -> io::Result<bool> {
+        let dir = match self.region_dir {
+            Some(ref dir) => dir,
+            None => return Ok(false),
+        };
+        
+        match self.sectors.get(&pos) {
+            Some(sector) => region::save(dir, pos, sector.blocks()).map(|_| true),
+            None => Ok(false),
         }
+    }
+    
+    /// Copy the blocks of every loaded sector, keyed by sector coordinates.
+    pub fn snapshot(&self) -> HashMap<(i32, i32, i32), BlockList> {
+        self.sectors.iter().map(|(&k, s)| (k, s.blocks().clone())).collect()
+    }
+    
+    /// Replace the whole world with these sectors, as from `snapshot`.
+    /// Like `clear`, anything still being generated is discarded, so
+    /// the world is exactly the given sectors until the next `update`
+    /// streams in more. This is meant for tests that need an exact
+    /// block layout without waiting on the generation threads.
+    ///
+    /// The sectors are meshed by later calls to `update`. Until then,
+    /// `block_at` sees them, but collision does not.
+    pub fn load_snapshot(&mut self, sectors: HashMap<(i32, i32, i32), BlockList>) {
+        self.clear();
         
-        //
+        for (coords, blocks) in sectors {
+            self.sectors.insert(coords, Sector::new(blocks, GenerationCause::Snapshot));
+        }
+    }
+    
+    /// Choose how sectors bordering ungenerated sectors are meshed.
+    /// Takes effect for sectors meshed after the call.
+    pub fn set_frontier_faces(&mut self, frontier_faces: FrontierFaces) {
+        self.frontier_faces = frontier_faces;
         
-        {
-            let front_t = Translation::new(translation.x, translation.y, translation.z.round() + 1.);
-            let front = match self.get_visible_block(&front_t) {
-                Some(f) => !f.is_air(),
-                None => false,
-            };
-            
-            let margin = front_t.z - 1. - COLLIDE_PADDING;
-            if front && translation.z > margin {
-                translation.z = margin;
-            }
+        for sector in self.sectors.values_mut() {
+            sector.invalidate_face_masks();
+        }
+    }
+    
+    /// Return how sectors bordering ungenerated sectors are meshed.
+    pub fn frontier_faces(&self) -> FrontierFaces {
+        self.frontier_faces
+    }
+    
+    /// Set the bias for smoothing distant textures. At `0` (the default)
+    /// the atlas is sampled once per fragment. Above `0`, fragments whose
+    /// footprint times the bias exceeds one texel average four samples,
+    /// trading three extra texture fetches for less shimmer.
+    pub fn set_texel_lod_bias(&mut self, bias: f32) {
+        self.texel_lod_bias = bias.max(0.);
+    }
+    
+    /// Get the bias for smoothing distant textures.
+    pub fn texel_lod_bias(&self) -> f32 {
+        self.texel_lod_bias
+    }
+    
+    /// Enable or disable frustum culling. When disabled, every
+    /// loaded sector is drawn, which is useful for debugging
+    /// culling and for measuring worst case performance.
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+    
+    /// Determine if frustum culling is enabled.
+    pub fn frustum_culling(&self) -> bool {
+        self.frustum_culling
+    }
+    
+    /// Get how many sectors the last draw drew and skipped,
+    /// for tuning frustum culling and the render radius.
+    pub fn last_render_stats(&self) -> RenderStats {
+        self.render_stats.get()
+    }
+    
+    /// Enable or disable sorting transparent faces back to front.
+    ///
+    /// Transparent blocks are drawn after all opaque ones, sector by
+    /// sector from the farthest to the nearest. With sorting, the faces
+    /// within each sector are also ordered from the camera, and are
+    /// resorted whenever the camera moves to another block. Disabling
+    /// it saves that work, but overlapping water may blend wrongly.
+    pub fn set_transparent_sorting(&mut self, enabled: bool) {
+        self.sort_transparent = enabled;
+    }
+    
+    /// Determine if transparent faces are sorted.
+    pub fn transparent_sorting(&self) -> bool {
+        self.sort_transparent
+    }
+    
+    /// Enable or disable merging neighboring faces that look the
+    /// same into larger quads when meshing. This greatly reduces the
+    /// vertices of flat terrain, at the cost of slower meshing.
+    /// Takes effect for sectors meshed after the call.
+    pub fn set_greedy_meshing(&mut self, enabled: bool) {
+        self.greedy_meshing = enabled;
+    }
+    
+    /// Determine if greedy meshing is enabled.
+    pub fn greedy_meshing(&self) -> bool {
+        self.greedy_meshing
+    }
+    
+    /// Enable or disable drawing the opaque terrain as the edges of
+    /// its triangles, to inspect meshing and culling. Every loaded
+    /// sector is queued to be remeshed, and each is drawn solid until
+    /// it is. Transparent faces are always drawn solid.
+    pub fn set_wireframe(&mut self, enabled: bool) {
+        if enabled == self.wireframe {
+            return;
         }
         
-        //
+        self.wireframe = enabled;
+        self.remesh.extend(self.sectors.keys().cloned());
+    }
+    
+    /// Determine if the terrain is drawn as a wireframe.
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+    
+    /// Enable or disable blending ground textures into a different
+    /// ground block along the X axis, softening material boundaries.
+    /// Takes effect for sectors meshed after the call.
+    ///
+    /// Blended fragments cost one extra texture fetch, or four with
+    /// a positive texel LOD bias.
+    pub fn set_edge_blending(&mut self, enabled: bool) {
+        self.edge_blending = enabled;
+    }
+    
+    /// Determine if edge blending is enabled.
+    pub fn edge_blending(&self) -> bool {
+        self.edge_blending
+    }
+    
+    /// Make `block` emit light of this color, with each channel from
+    /// `0` to `MAX_LIGHT`, or stop it emitting light with `None`.
+    /// Light spreads through air, fading by one level per block in
+    /// every channel. Takes effect for sectors meshed after the call.
+    pub fn set_light_color(&mut self, block: Block, color: Option<LightColor>) {
+        match color {
+            Some(c) => {
+                let c = [c[0].min(MAX_LIGHT), c[1].min(MAX_LIGHT), c[2].min(MAX_LIGHT)];
+                self.light_colors.insert(block, c);
+            },
+            None => {
+                self.light_colors.remove(&block);
+            },
+        }
+    }
+    
+    /// Get the color of light `block` emits, if any.
+    pub fn light_color(&self, block: Block) -> Option<LightColor> {
+        self.light_colors.get(&block).cloned()
+    }
+    
+    /// Advance the block simulation by one fixed step of `dt` seconds.
+    /// Unlike `update`, this is called at a steady rate regardless
+    /// of the frame rate, so simulation stays deterministic.
+    pub fn tick(&mut self, dt: f32) {
+        self.particles.tick(dt);
         
-        {
-            let above_t = Translation::new(translation.x, translation.y.round() + 1., translation.z);
-            let above = match self.get_visible_block(&above_t) {
-                Some(a) => !a.is_air(),
-                None => false,
-            };
-            
-            let margin = above_t.y - 1. - COLLIDE_PADDING;
-            if above && translation.y > margin {
-                translation.y = margin;
-            }
+        self.ticks += 1;
+        self.time += dt;
+    }
+    
+    /// Enable or disable the particle burst shown when a block breaks.
+    pub fn set_break_particles(&mut self, enabled: bool) {
+        self.break_particles = enabled;
+    }
+    
+    /// Spawn the particle burst for `block` breaking at this position
+    /// in **world** block coordinates, if break particles are enabled.
+    /// The total number of particles is capped at `particles::MAX_PARTICLES`.
+    pub fn spawn_break_particles(&mut self, world_pos: (i32, i32, i32), block: Block) {
+        if !self.break_particles || block.is_air() {
+            return;
         }
         
-        //
+        let size = SECTOR_SIZE as i32;
+        let pos = [(world_pos.0 - self.origin.0 * size) as f32,
+                   (world_pos.1 - self.origin.1 * size) as f32,
+                   (world_pos.2 - self.origin.2 * size) as f32];
         
-        {
-            let below_t = Translation::new(translation.x, translation.y.round() - 1., translation.z);
-            let below = match self.get_visible_block(&below_t) {
-                Some(b) => !b.is_air(),
-                None => false,
-            };
-            
-            let margin = below_t.y + 1. + COLLIDE_PADDING;
-            if below && translation.y < margin {
-                translation.y = margin;
+        let atlas = block.atlas();
+        let tile = mesh_gen::tex_coords(&block, Face::Front, &self.resources.terrain_layouts()[atlas]);
+        self.particles.burst(pos, tile, atlas);
+    }
+    
+    /// Return the number of simulation ticks run so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+    
+    /// Return why the sector at these coordinates was generated,
+    /// or `None` if it is not loaded. Intended for debugging.
+    pub fn generation_cause(&self, sector: (i32, i32, i32)) -> Option<GenerationCause> {
+        self.sectors.get(&sector).map(|s| s.cause())
+    }
+    
+    /// Return the number of vertices and triangles in the model of the
+    /// sector at these coordinates, or `None` if it is not loaded.
+    /// Sectors without a model have no vertices.
+    pub fn sector_mesh_size(&self, sector: (i32, i32, i32)) -> Option<(usize, usize)> {
+        self.sectors.get(&sector).map(|s| (s.vertex_count(), s.triangle_count()))
+    }
+    
+    /// Return the total number of vertices in all loaded models.
+    pub fn vertex_count(&self) -> usize {
+        self.sectors.values().map(|s| s.vertex_count()).sum()
+    }
+    
+    /// Determine if every sector in the inclusive range
+    /// from `min_sector` to `max_sector` has been generated.
+    /// Empty (all air) sectors count as generated.
+    pub fn region_ready(&self, min_sector: (i32, i32, i32), max_sector: (i32, i32, i32)) -> bool {
+        self.region_progress(min_sector, max_sector) >= 1.
+    }
+    
+    /// Get the fraction, from `0` to `1`, of the sectors in the
+    /// inclusive range from `min_sector` to `max_sector` that
+    /// have been generated. An empty range is complete.
+    pub fn region_progress(&self, min_sector: (i32, i32, i32), max_sector: (i32, i32, i32)) -> f32 {
+        let mut total = 0;
+        let mut loaded = 0;
+        
+        for x in min_sector.0..max_sector.0 + 1 {
+            for y in min_sector.1..max_sector.1 + 1 {
+                for z in min_sector.2..max_sector.2 + 1 {
+                    total += 1;
+                    
+                    if self.sectors.contains_key(&(x, y, z)) {
+                        loaded += 1;
+                    }
+                }
             }
-            
-            //println!("{:?}, {:?}", self.get_visible_block(&below_t), *translation);
-
         }
         
-        //
+        if total == 0 {
+            1.
+        } else {
+            loaded as f32 / total as f32
+        }
+    }
+    
+    /// Return the number of sectors waiting to be remeshed because
+    /// a neighbor was loaded or blocks were edited. Intended for
+    /// debugging.
+    pub fn pending_remeshes(&self) -> usize {
+        self.remesh.len()
+    }
+    
+    /// Return the absolute sector containing the camera.
+    /// See `sector_at` for how positions are rounded.
+    pub fn current_sector(&self, camera: &Camera) -> (i32, i32, i32) {
+        self.world_sector_at(camera.translation())
+    }
+    
+    /// Return the absolute sector that contained the camera at
+    /// the last `update`, or `None` before the first one.
+    pub fn camera_sector(&self) -> Option<(i32, i32, i32)> {
+        self.camera_sector
+    }
+    
+    /// Determine if the camera moved into another sector during the
+    /// last `update`. The first update only places the camera, so it
+    /// never counts as a crossing.
+    pub fn just_crossed_sector(&self) -> bool {
+        self.crossed_sector
+    }
+    
+    /// Return the render origin, in sector coordinates.
+    /// Local positions are relative to this sector's corner.
+    pub fn origin(&self) -> (i32, i32, i32) {
+        self.origin
+    }
+    
+    // Gather references to the neighbors of a sector.
+    fn adjacent_sectors<'s>(&'s self, sector_coords: (i32, i32, i32)) -> AdjacentSectors<'s> {
+        AdjacentSectors::from_map(&self.sectors, sector_coords)
+    }
+    
+    // Queue the meshed neighbors of a newly loaded sector,
+    // since their boundary faces may now be hidden or exposed.
+    fn mark_neighbors_for_remesh(&mut self, sector_coords: (i32, i32, i32)) {
+        let (x, y, z) = sector_coords;
+        let neighbors = [(x, y, z - 1), (x, y, z + 1),
+                         (x, y + 1, z), (x, y - 1, z),
+                         (x - 1, y, z), (x + 1, y, z)];
         
-        {
-            let left_t = Translation::new(translation.x.round() - 1., translation.y, translation.z);
-            let left = match self.get_visible_block(&left_t) {
-                Some(l) => !l.is_air(),
-                None => false,
-            };
+        for n in &neighbors {
+            if let Some(sector) = self.sectors.get_mut(n) {
+                sector.invalidate_face_masks();
+                
+                if sector.model().is_some() {
+                    self.remesh.insert(*n);
+                }
+            }
+        }
+    }
+    
+    // Mark the cached faces of the block at this position in world
+    // coords, and of its six neighbors, as stale. The sectors that
+    // were marked are added to `touched`.
+    fn mark_block_dirty(&mut self, world_pos: (i32, i32, i32),
+                        touched: &mut HashSet<(i32, i32, i32)>) {
+        for &pos in &block_and_neighbors(world_pos) {
+            let (sector_coords, local) = split_world_coords(pos);
             
-            let margin = left_t.x + 1. + COLLIDE_PADDING;
-            if left && translation.x < margin {
-                translation.x = margin;
+            if let Some(sector) = self.sectors.get_mut(&sector_coords) {
+                sector.mark_dirty(local);
+                touched.insert(sector_coords);
             }
         }
-        
-        //
-        
-        {
-            let right_t = Translation::new(translation.x.round() + 1., translation.y, translation.z);
-            let right = match self.get_visible_block(&right_t) {
-                Some(r) => !r.is_air(),
-                None => false,
+    }
+    
+    // Remesh queued sectors, up to `MAX_REMESH_PER_UPDATE` of them.
+    // Past the `MAX_LAG` budget, counted from `begin`, only one more
+    // is remeshed, so that edits still show up under heavy loading.
+    fn rebuild_dirty(&mut self, begin: Instant) {
+        for i in 0..MAX_REMESH_PER_UPDATE {
+            if i > 0 && seconds_since(begin) > MAX_LAG {
+                break;
+            }
+            
+            let sector_coords = match self.remesh.iter().next() {
+                Some(&c) => c,
+                None => break,
             };
+            self.remesh.remove(&sector_coords);
             
-            let margin = right_t.x - 1. - COLLIDE_PADDING;
-            if right && translation.x > margin {
-                translation.x = margin;
+            let meshed = self.sectors.get(&sector_coords).map_or(false, |s| s.model().is_some());
+            if !meshed {
+                continue;
             }
+            
+            if self.frontier_faces == FrontierFaces::Wait &&
+               !self.adjacent_sectors(sector_coords).is_complete() {
+                continue;
+            }
+            
+            self.remesh_sector(sector_coords);
         }
     }
     
-    // Get the block at this position in **world** coords.
-    // If the sector is generated but not rendered, `None`
-    // is returned.
-    fn get_visible_block(&self, pos: &Translation) -> Option<&Block> {
-        let sector_pos = sector_at(pos);
-        
-        let pos = (pos.x.round() as i32, pos.y.round() as i32, pos.z.round() as i32);
+    // Build the model of a loaded sector against its current neighbors.
+    fn remesh_sector(&mut self, sector_coords: (i32, i32, i32)) {
+        // Take the sector out of the map while it is meshed, since
+        // it updates its own face cache while its neighbors are borrowed.
+        let mut sector = match self.sectors.remove(&sector_coords) {
+            Some(s) => s,
+            None => return,
+        };
         
-        if let Some(sector) = self.sectors.get(&sector_pos) {
-            if sector.model().is_none() && sector.blocks().needs_rendering() {
-                return None;
-            }
+        let model;
+        let frontier;
+        {
+            let adjacent = self.adjacent_sectors(sector_coords);
+            frontier = !adjacent.is_complete();
             
-            let local = SectorSpaceCoords::new((pos.0 - sector_pos.0 * SECTOR_SIZE as i32) as u8,
-                                               (pos.1 - sector_pos.1 * SECTOR_SIZE as i32) as u8,
-                                               (pos.2 - sector_pos.2 * SECTOR_SIZE as i32) as u8);
+            let local = (sector_coords.0 - self.origin.0,
+                         sector_coords.1 - self.origin.1,
+                         sector_coords.2 - self.origin.2);
             
-            Some(sector.blocks().get(local))
-        } else {
-            None
+            model = sector.create_model(self.resources, local, &adjacent,
+                                        self.frontier_faces == FrontierFaces::Draw,
+                                        self.edge_blending, self.greedy_meshing,
+                                        self.wireframe, &self.light_colors);
         }
+        
+        sector.set_model(model);
+        sector.set_frontier(frontier);
+        self.sectors.insert(sector_coords, sector);
     }
     
-    fn load_shaders() ->
-            Result<(Program<Vertex, (), Uniforms>, Vec<UniformWarning>), ProgramError> {
+    // Move the origin to the camera's sector once the camera
+    // strays beyond `REBASE_THRESHOLD`, shifting the camera and
+    // every model so nothing visibly moves.
+    fn rebase(&mut self, camera: &mut Camera) {
+        let shift = sector_at(camera.translation());
+        
+        if shift.0.abs() < REBASE_THRESHOLD &&
+           shift.1.abs() < REBASE_THRESHOLD &&
+           shift.2.abs() < REBASE_THRESHOLD {
+            return;
+        }
+        
+        let offset = ((shift.0 * SECTOR_SIZE as i32) as f32,
+                      (shift.1 * SECTOR_SIZE as i32) as f32,
+                      (shift.2 * SECTOR_SIZE as i32) as f32);
+        
+        camera.translation_mut().slide(-offset.0, -offset.1, -offset.2);
+        
+        for sector in self.sectors.values_mut() {
+            if let Some(model) = sector.model_mut() {
+                model.translation.slide(-offset.0, -offset.1, -offset.2);
+            }
+        }
         
-        let (vs, fs) = shader::load_shader_text("vs", "fs");
+        self.particles.shift([-offset.0, -offset.1, -offset.2]);
         
-        Program::from_strings(None, &vs, None, &fs)
+        self.origin = (self.origin.0 + shift.0,
+                       self.origin.1 + shift.1,
+                       self.origin.2 + shift.2);
     }
-}
-
-impl<'a> Drawable for Terrain<'a> {
-    //type Vertex = TerrainVertex;
-    //type Uniform = TerrainUniforms;
     
-    fn draw(&self,
-            device: &mut GLFWDevice,
-            render_target: &Framebuffer<Flat, Dim2, (), ()>,
-            //shader: &Program<Self::Vertex, (), Self::Uniform>,
-            camera: &Camera) {
-        let frustum = camera.frustum();
+    // The absolute sector containing a local position.
+    fn world_sector_at(&self, pos: &Translation) -> (i32, i32, i32) {
+        let local = sector_at(pos);
         
-        device.draw(|| {
-            entry(|gpu| {                    
-                // TODO: Only bind the texture once, and ensure
-                // that the correct one is used.
-                pipeline(render_target, CLEAR_COLOR, |shade_gate| {
-                    //let mut skipped = 0;
-                    //let mut air = 0;
-                    
-                    for i in &self.sectors {
-                        if let Some(model) = i.1.model() {
-                            if !sector_visible(&frustum, *i.0) {
-                                //skipped += 1;
-                                continue;
-                            }
-                            
-                            gpu.bind_texture(&model.tex.0);
-                            shade_gate.shade(&self.shader, |render_gate, uniforms| {
-                                uniforms.model_matrix.update(model.to_matrix());
-                                uniforms.view_matrix.update(camera.to_matrix());
-                                uniforms.projection_matrix.update(*camera.projection_matrix());
-                                //uniforms.terrain_tex.update(bound);
-                                
-                                let render_state = RenderState::default();
-                                                   //.set_face_culling(None);
-                                render_gate.render(render_state, |tess_gate| {
-                                    tess_gate.render((&model.tess).into());
-                                });
-                            });
-                        }/* else {
-                            air += 1;
-                        }*/
-                    }
-                    
-                    //println!("skipped: {} / {})", skipped, self.sectors.len() - air);
-                });
-            });
-        });
+        (local.0 + self.origin.0,
+         local.1 + self.origin.1,
+         local.2 + self.origin.2)
     }
-}
-
-/// Terrain's uniform interface.
-struct Uniforms {
-    // Model transform.
-    model_matrix: Uniform<M44>,
     
-    // Camera view.
-    view_matrix: Uniform<M44>,
+    /// Adjust for collisions with the terrain, and report what
+    /// was touched.
+    ///
+    /// Moving sideways into a ledge no taller than `step_height`,
+    /// with room above it, lifts the translation onto the ledge
+    /// instead of pushing it back. Pass `DEFAULT_STEP_HEIGHT` for
+    /// single block steps, or `0.` to disable stepping.
+    pub fn collide(&self, translation: &mut Translation, step_height: f32) -> CollisionResult {
+        collide(&|t: &Translation| self.get_visible_block(t).cloned(), translation, step_height)
+    }
+    
+    /// Get a copy of the block at this position in absolute **world**
+    /// block coordinates. Negative coordinates are supported.
+    ///
+    /// `None` is returned if the owning sector has not been generated,
+    /// or has been unloaded. Unlike collision, a sector does not need
+    /// to be rendered for its blocks to be read.
+    pub fn block_at(&self, world_pos: (i32, i32, i32)) -> Option<Block> {
+        let (sector_pos, local) = split_world_coords(world_pos);
+        
+        self.sectors.get(&sector_pos).map(|s| *s.blocks().get(local))
+    }
+    
+    /// Find the first block that is not air along a ray, walking the
+    /// block grid from `origin`, relative to the render origin like the
+    /// camera, in direction `dir`. Blocks in sectors that are not loaded
+    /// are passed through. Gives up after `max_dist` blocks.
+    pub fn raycast(&self, origin: &Translation, dir: Vec3, max_dist: f32) -> Option<RayHit> {
+        let dir = dir.normalize();
+        if dir.length_squared() == 0. {
+            return None;
+        }
+        
+        let size = SECTOR_SIZE as i32;
+        let o = [origin.x, origin.y, origin.z];
+        let d = [dir.x, dir.y, dir.z];
+        
+        let mut cell = [o[0].floor() as i32, o[1].floor() as i32, o[2].floor() as i32];
+        let mut step = [0; 3];
+        let mut t_max = [::std::f32::INFINITY; 3];
+        let mut t_delta = [::std::f32::INFINITY; 3];
+        
+        // The distance to the first boundary on each axis, and between them.
+        for i in 0..3 {
+            if d[i] > 0. {
+                step[i] = 1;
+                t_max[i] = (cell[i] as f32 + 1. - o[i]) / d[i];
+                t_delta[i] = 1. / d[i];
+            } else if d[i] < 0. {
+                step[i] = -1;
+                t_max[i] = (o[i] - cell[i] as f32) / -d[i];
+                t_delta[i] = 1. / -d[i];
+            }
+        }
+        
+        let mut normal = [0; 3];
+        loop {
+            let world_pos = (cell[0] + self.origin.0 * size,
+                             cell[1] + self.origin.1 * size,
+                             cell[2] + self.origin.2 * size);
+            
+            if self.block_at(world_pos).map_or(false, |b| !b.is_air()) {
+                return Some(RayHit {
+                    pos: world_pos,
+                    normal: (normal[0], normal[1], normal[2]),
+                });
+            }
+            
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] { 0 } else { 2 }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            
+            if t_max[axis] > max_dist {
+                return None;
+            }
+            
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            
+            normal = [0; 3];
+            normal[axis] = -step[axis];
+        }
+    }
+    
+    /// Call `handler` for every block changed by `set_block` or
+    /// `map_region`, after the terrain has been updated. This lets
+    /// applications react to edits, such as with sounds, without
+    /// the terrain knowing about them.
+    pub fn set_block_event_handler(&mut self, handler: Box<dyn FnMut(BlockEvent)>) {
+        self.block_event_handler = Some(handler);
+    }
+    
+    /// Remove the block event handler, if any.
+    pub fn clear_block_event_handler(&mut self) {
+        self.block_event_handler = None;
+    }
+    
+    /// Replace the block at this position in absolute **world** block
+    /// coordinates. Returns `false` if the owning sector is not loaded.
+    /// The change is drawn once the next `update` remeshes the sector.
+    pub fn set_block(&mut self, world_pos: (i32, i32, i32), block: Block) -> bool {
+        if !self.sectors.contains_key(&split_world_coords(world_pos).0) {
+            return false;
+        }
+        
+        self.edit_region(world_pos, world_pos, |_, _| block, BlockEventCause::SetBlock);
+        true
+    }
+    
+    /// Replace every block in the inclusive box from `min_world` to
+    /// `max_world`, in absolute **world** block coordinates, with the
+    /// result of `f`, which is given the position and current block.
+    ///
+    /// The box may span many sectors. Blocks in sectors that are not
+    /// loaded are skipped. Each changed sector, and each neighbor whose
+    /// boundary faces changed, is queued to be remeshed once by a later
+    /// `update`. Returns the number of blocks changed.
+    pub fn map_region<F>(&mut self, min_world: (i32, i32, i32), max_world: (i32, i32, i32),
+                         f: F) -> usize
+        where F: FnMut((i32, i32, i32), Block) -> Block {
+        self.edit_region(min_world, max_world, f, BlockEventCause::MapRegion)
+    }
+    
+    // Implements `map_region`, reporting edits with this cause.
+    fn edit_region<F>(&mut self, min_world: (i32, i32, i32), max_world: (i32, i32, i32),
+                      mut f: F, cause: BlockEventCause) -> usize
+        where F: FnMut((i32, i32, i32), Block) -> Block {
+        let (min_sector, _) = split_world_coords(min_world);
+        let (max_sector, _) = split_world_coords(max_world);
+        let size = SECTOR_SIZE as i32;
+        
+        let mut changed = 0;
+        let mut changed_blocks = Vec::new();
+        
+        for sx in min_sector.0..max_sector.0 + 1 {
+            for sy in min_sector.1..max_sector.1 + 1 {
+                for sz in min_sector.2..max_sector.2 + 1 {
+                    let sector_coords = (sx, sy, sz);
+                    let sector = match self.sectors.get_mut(&sector_coords) {
+                        Some(s) => s,
+                        None => continue,
+                    };
+                    
+                    // The part of the box inside this sector, in world coords.
+                    let lo = (min_world.0.max(sx * size), min_world.1.max(sy * size), min_world.2.max(sz * size));
+                    let hi = (max_world.0.min(sx * size + size - 1),
+                              max_world.1.min(sy * size + size - 1),
+                              max_world.2.min(sz * size + size - 1));
+                    
+                    for x in lo.0..hi.0 + 1 {
+                        for y in lo.1..hi.1 + 1 {
+                            for z in lo.2..hi.2 + 1 {
+                                let (_, local) = split_world_coords((x, y, z));
+                                let block = *sector.blocks().get(local);
+                                let new_block = f((x, y, z), block);
+                                
+                                if new_block != block {
+                                    sector.blocks_mut().set(local, new_block);
+                                    changed_blocks.push(BlockEvent {
+                                        pos: (x, y, z),
+                                        old: block,
+                                        new: new_block,
+                                        cause,
+                                    });
+                                    changed += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        
+        // Sky light is computed per sector, so the edited ones are relit.
+        let edited: HashSet<_> = changed_blocks.iter()
+                                               .map(|e| split_world_coords(e.pos).0)
+                                               .collect();
+        for sector_coords in edited {
+            let blocks = self.sectors.get_mut(&sector_coords).unwrap().blocks_mut();
+            light::propagate_sky_light(blocks, light::open_to_sky(sector_coords));
+        }
+        
+        let mut touched = HashSet::new();
+        for event in &changed_blocks {
+            self.mark_block_dirty(event.pos, &mut touched);
+        }
+        
+        // Unmeshed sectors are meshed by `update` when they need to be.
+        for sector_coords in touched {
+            if self.sectors[&sector_coords].model().is_some() {
+                self.remesh.insert(sector_coords);
+            }
+        }
+        
+        if let Some(ref mut handler) = self.block_event_handler {
+            for event in changed_blocks {
+                handler(event);
+            }
+        }
+        
+        changed
+    }
+    
+    // Get the block at this position in **local** coords.
+    // If the sector is generated but not rendered, `None`
+    // is returned.
+    fn get_visible_block(&self, pos: &Translation) -> Option<&Block> {
+        let local_sector = sector_at(pos);
+        let sector_pos = self.world_sector_at(pos);
+        
+        let pos = (pos.x.round() as i32, pos.y.round() as i32, pos.z.round() as i32);
+        
+        if let Some(sector) = self.sectors.get(&sector_pos) {
+            if sector.model().is_none() && sector.blocks().needs_rendering() {
+                return None;
+            }
+            
+            let local = SectorSpaceCoords::new((pos.0 - local_sector.0 * SECTOR_SIZE as i32) as u8,
+                                               (pos.1 - local_sector.1 * SECTOR_SIZE as i32) as u8,
+                                               (pos.2 - local_sector.2 * SECTOR_SIZE as i32) as u8);
+            
+            Some(sector.blocks().get(local))
+        } else {
+            None
+        }
+    }
+    
+    // Draw the transparent faces of visible sectors, farthest first,
+    // blended over what is already drawn.
+    fn draw_transparent(&self, gpu: &Gpu, shade_gate: &ShadingGate,
+                        camera: &Camera, frustum: &Frustum) {
+        let eye = Vec3::from(camera.translation());
+        
+        let mut visible: Vec<_> = self.sectors.iter().filter_map(|(k, s)| {
+            let model = s.model()?;
+            let tess = s.transparent()?;
+            
+            let local = (k.0 - self.origin.0, k.1 - self.origin.1, k.2 - self.origin.2);
+            if self.frustum_culling && !sector_visible(frustum, local) {
+                return None;
+            }
+            
+            Some(((sector_center(local) - eye).length_squared(), model, tess))
+        }).collect();
+        
+        if visible.is_empty() {
+            return;
+        }
+        
+        visible.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        
+        gpu.bind_texture(&self.resources.terrain_tex().0);
+        for &(_, model, tess) in &visible {
+            shade_gate.shade(&self.shader, |render_gate, uniforms| {
+                uniforms.model_matrix.update(model.to_matrix());
+                uniforms.view_matrix.update(camera.to_matrix());
+                uniforms.projection_matrix.update(*camera.projection_matrix());
+                uniforms.texel_lod_bias.update(self.texel_lod_bias);
+                uniforms.time.update(self.time);
+                uniforms.opacity.update(TRANSPARENT_OPACITY);
+                uniforms.fog_color.update(self.fog_color);
+                uniforms.fog_distance.update(self.fog_distance);
+                uniforms.sun_direction.update(self.sun_direction);
+                
+                // Draw both sides, so the surface is seen from below.
+                let render_state = RenderState::default()
+                                   .set_blending((Equation::Additive,
+                                                  Factor::SrcAlpha,
+                                                  Factor::SrcAlphaComplement))
+                                   .set_face_culling(None);
+                render_gate.render(render_state, |tess_gate| {
+                    tess_gate.render(tess.into());
+                });
+            });
+        }
+    }
+    
+    /// Read the shaders again and rebuild the program, so edits to
+    /// the shader files show without restarting. If they can not be
+    /// read or fail to compile, the error is returned and the old
+    /// program is kept.
+    pub fn reload_shaders(&mut self) -> Result<(), ShaderError> {
+        let (shader, warnings) = Self::load_shaders(self.shader_source)?;
+        for warn in &warnings {
+            eprintln!("{:?}", warn);
+        }
+        
+        self.shader = shader;
+        
+        Ok(())
+    }
+    
+    fn load_shaders(shaders: ShaderSource) ->
+            Result<(Program<Vertex, (), Uniforms>, Vec<UniformWarning>), ShaderError> {
+        
+        let (vs, fs) = shaders.text()?;
+        
+        Ok(Program::from_strings(None, &vs, None, &fs)?)
+    }
+}
+
+impl<'a> Drawable for Terrain<'a> {
+    //type Vertex = TerrainVertex;
+    //type Uniform = TerrainUniforms;
+    
+    fn draw_in(&self, gpu: &Gpu, shade_gate: &ShadingGate, camera: &Camera) {
+        let frustum = camera.frustum();
+        
+        // The scratch list is kept between frames, so
+        // that collecting it does not allocate.
+        let mut order = self.draw_order.borrow_mut();
+        
+        let meshed = self.sectors.iter().map(|(&pos, s)| (pos, s.model().is_some()));
+        let culling = if self.frustum_culling { Some(&frustum) } else { None };
+        let mut stats = plan_draw(meshed, self.origin, culling,
+                                  Vec3::from(camera.translation()), &mut order);
+        
+        sort_nearest_first(&mut order);
+        
+        stats.sectors_drawn = order.len();
+        self.render_stats.set(stats);
+        
+        // Sectors are batched by atlas, so each atlas is bound once.
+        // A sector with faces in several atlases costs one draw call
+        // for each of them, so blocks that appear together should
+        // share an atlas where possible.
+        for (atlas, tex) in self.resources.terrain_atlases().iter().enumerate() {
+            gpu.bind_texture(&tex.0);
+            
+            for &(_, pos) in order.iter() {
+                let sector = &self.sectors[&pos];
+                let wireframe = if self.wireframe { sector.wireframe_batch(atlas) } else { None };
+                let batch = wireframe.or_else(|| sector.atlas_batch(atlas));
+                let (model, tess) = match (sector.model(), batch) {
+                    (Some(m), Some(t)) => (m, t),
+                    _ => continue,
+                };
+                
+                shade_gate.shade(&self.shader, |render_gate, uniforms| {
+                    uniforms.model_matrix.update(model.to_matrix());
+                    uniforms.view_matrix.update(camera.to_matrix());
+                    uniforms.projection_matrix.update(*camera.projection_matrix());
+                    uniforms.texel_lod_bias.update(self.texel_lod_bias);
+                    uniforms.time.update(self.time);
+                    uniforms.opacity.update(1.);
+                    uniforms.fog_color.update(self.fog_color);
+                    uniforms.fog_distance.update(self.fog_distance);
+                    uniforms.sun_direction.update(self.sun_direction);
+                    //uniforms.terrain_tex.update(bound);
+                    
+                    let render_state = RenderState::default();
+                                       //.set_face_culling(None);
+                    render_gate.render(render_state, |tess_gate| {
+                        tess_gate.render(tess.into());
+                    });
+                });
+            }
+        }
+        
+        //println!("{:?}", stats);
+        mem::drop(order);
+        
+        self.draw_transparent(gpu, shade_gate, camera, &frustum);
+        
+        for (atlas, tex) in self.resources.terrain_atlases().iter().enumerate() {
+            if self.particles.is_empty() {
+                break;
+            }
+            
+            let vertices = self.particles.vertices(&camera.to_matrix(), atlas);
+            if vertices.is_empty() {
+                continue;
+            }
+            
+            let tess = Tess::new(Mode::Triangle, TessVertices::Fill(&vertices), None);
+            
+            gpu.bind_texture(&tex.0);
+            shade_gate.shade(&self.shader, |render_gate, uniforms| {
+                uniforms.model_matrix.update(maths::IDENTITY);
+                uniforms.view_matrix.update(camera.to_matrix());
+                uniforms.projection_matrix.update(*camera.projection_matrix());
+                uniforms.texel_lod_bias.update(self.texel_lod_bias);
+                uniforms.time.update(self.time);
+                uniforms.opacity.update(1.);
+                uniforms.fog_color.update(self.fog_color);
+                uniforms.fog_distance.update(self.fog_distance);
+                uniforms.sun_direction.update(self.sun_direction);
+                
+                let render_state = RenderState::default()
+                                   .set_face_culling(None);
+                render_gate.render(render_state, |tess_gate| {
+                    tess_gate.render((&tess).into());
+                });
+            });
+        }
+    }
+    
+    fn render<CS, DS>(&self,
+                      render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                      //shader: &Program<Self::Vertex, (), Self::Uniform>,
+                      camera: &Camera)
+        where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+        entry(|gpu| {
+            pipeline(render_target, CLEAR_COLOR, |shade_gate| {
+                self.draw_in(&gpu, &shade_gate, camera);
+            });
+        });
+    }
+}
+
+/// Terrain's uniform interface.
+struct Uniforms {
+    // Model transform.
+    model_matrix: Uniform<M44>,
+    
+    // Camera view.
+    view_matrix: Uniform<M44>,
     
     // 3D Projection.
     projection_matrix: Uniform<M44>,
     
+    // Bias for smoothing distant textures.
+    texel_lod_bias: Uniform<f32>,
+    
+    // Simulation time in seconds, for animation.
+    time: Uniform<f32>,
+    
+    // Alpha applied to the whole draw.
+    opacity: Uniform<f32>,
+    
+    // Color that distant terrain fades into.
+    fog_color: Uniform<[f32; 3]>,
+    
+    // Distance at which terrain is fully fogged, or 0 for no fog.
+    fog_distance: Uniform<f32>,
+    
+    // Direction toward the sun, for shading faces.
+    sun_direction: Uniform<[f32; 3]>,
+    
     // Terrain Texture Atlas.
     //pub terrain_tex: Uniform<BoundTexture<'a, Texture<Flat, Dim2, RGB8UI>>>,
 }
@@ -406,25 +1354,62 @@ impl<'a> UniformInterface for Uniforms {
     fn uniform_interface(builder: UniformBuilder)
             -> Result<(Uniforms, Vec<UniformWarning>), ProgramError> {
         
-        let model_matrix = builder.ask("model_matrix").unwrap();
-        let view_matrix = builder.ask("view_matrix").unwrap();
-        let projection_matrix = builder.ask("projection_matrix").unwrap();
+        // A uniform the shader does not use is optimized out, which
+        // is only a warning, so the program is still usable.
+        let mut warnings = Vec::new();
+        
+        let model_matrix = ask_uniform(&builder, "model_matrix", &mut warnings);
+        let view_matrix = ask_uniform(&builder, "view_matrix", &mut warnings);
+        let projection_matrix = ask_uniform(&builder, "projection_matrix", &mut warnings);
+        let texel_lod_bias = ask_uniform(&builder, "texel_lod_bias", &mut warnings);
+        let time = ask_uniform(&builder, "time", &mut warnings);
+        let opacity = ask_uniform(&builder, "opacity", &mut warnings);
+        let fog_color = ask_uniform(&builder, "fog_color", &mut warnings);
+        let fog_distance = ask_uniform(&builder, "fog_distance", &mut warnings);
+        let sun_direction = ask_uniform(&builder, "sun_direction", &mut warnings);
         //let terrain_tex = builder.ask("terrain_tex").unwrap();
         
         Ok((Uniforms {
             model_matrix,
             view_matrix,
             projection_matrix,
+            texel_lod_bias,
+            time,
+            opacity,
+            fog_color,
+            fog_distance,
+            sun_direction,
             //terrain_tex,
-        }, Vec::new()))
+        }, warnings))
     }
 }
 
+// Get the uniform called `name`. If the shader does not have it,
+// the warning is recorded and an unbound uniform, whose updates
+// are ignored, is used instead.
+fn ask_uniform<T>(builder: &UniformBuilder, name: &str,
+                  warnings: &mut Vec<UniformWarning>) -> Uniform<T>
+    where T: Uniformable {
+    builder.ask(name).unwrap_or_else(|warning| {
+        warnings.push(warning);
+        builder.unbound()
+    })
+}
+
 // Information shared between the main thread
 // and the worldgen thread.
 #[derive(Debug)]
 struct WorldGenThreadInfo {
-     player_pos: Translation,
+     player_sector: (i32, i32, i32),
+     render_radius: i32,
+     generate_radius: i32,
+     max_pending: usize,
+     scan_interval: Duration,
+     region_dir: Option<PathBuf>,
+     
+     // Incremented by `Terrain::clear`, to tell generated
+     // sectors from before and after the clear apart.
+     epoch: u32,
 }
 
 type SharedInfo = Arc<Mutex<WorldGenThreadInfo>>;
@@ -432,158 +1417,874 @@ type SharedInfo = Arc<Mutex<WorldGenThreadInfo>>;
 impl Default for WorldGenThreadInfo {
     fn default() -> WorldGenThreadInfo {
         WorldGenThreadInfo {
-            player_pos: Translation::new(0., 0., 0.),
+            player_sector: (0, 0, 0),
+            render_radius: DEFAULT_RENDER_RADIUS,
+            generate_radius: DEFAULT_GENERATE_RADIUS,
+            max_pending: DEFAULT_MAX_PENDING_REQUESTS,
+            scan_interval: DEFAULT_SCAN_INTERVAL,
+            region_dir: None,
+            epoch: 0,
         }
     }
 }
 
+/// How to mesh the faces of a sector that border a neighbor which
+/// has not been generated yet. Sectors meshed with missing neighbors
+/// are meshed again once all six neighbors are loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontierFaces {
+    /// Do not mesh a sector until all of its neighbors are loaded.
+    Wait,
+    
+    /// Draw faces that border missing neighbors. There are never holes,
+    /// but the extra faces are wasted once the neighbor loads.
+    Draw,
+    
+    /// Cull faces that border missing neighbors. Fewer vertices are
+    /// drawn, but holes show briefly until the neighbors arrive.
+    Cull,
+}
+
+/// Counts of the sectors considered by the last draw.
+/// See `Terrain::last_render_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Every loaded sector.
+    pub sectors_total: usize,
+    
+    /// Sectors with a mesh that were drawn.
+    pub sectors_drawn: usize,
+    
+    /// Sectors with a mesh that were outside the view frustum.
+    pub sectors_culled: usize,
+    
+    /// Sectors without a mesh, because they are all air
+    /// or are still waiting to be meshed.
+    pub sectors_empty: usize,
+}
+
+/// What `Terrain::collide` found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollisionResult {
+    /// The translation was moved, out of a block or up a step.
+    pub corrected: bool,
+    
+    /// The translation is standing on a block.
+    pub grounded: bool,
+    
+    /// The translation was stopped by a block above it.
+    pub hit_ceiling: bool,
+    
+    /// The translation was stopped by a block beside it.
+    /// Stepping up onto a ledge does not count.
+    pub hit_wall: bool,
+}
+
+/// The block found by `Terrain::raycast`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayHit {
+    /// The position of the block, in absolute world block coordinates.
+    pub pos: (i32, i32, i32),
+    
+    /// The direction out of the face the ray entered through, along
+    /// one axis. Adding it to `pos` gives the block in front of that
+    /// face, where a new block would be placed. It is all zero if the
+    /// ray started inside the block.
+    pub normal: (i32, i32, i32),
+}
+
+/// A change to a single block, passed to the block event handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockEvent {
+    /// The position of the block, in absolute world block coordinates.
+    pub pos: (i32, i32, i32),
+    
+    /// The block before the change.
+    pub old: Block,
+    
+    /// The block after the change.
+    pub new: Block,
+    
+    /// What made the change.
+    pub cause: BlockEventCause,
+}
+
+/// What changed a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockEventCause {
+    /// A call to `Terrain::set_block`.
+    SetBlock,
+    
+    /// A call to `Terrain::map_region`.
+    MapRegion,
+}
+
+/// The reason a sector was generated. Only used for debugging,
+/// and small enough to be carried by every `Sector` for free.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenerationCause {
+    /// The sector was requested because it came within range of the camera.
+    Streaming,
+    
+    /// The sector was loaded by `Terrain::load_snapshot`.
+    Snapshot,
+    
+    /// The sector was read from the region directory instead of
+    /// generated. See `Terrain::set_region_dir`.
+    Disk,
+}
+
 // Type for the 'nearby sector' channel.
 enum Nearby {
     Query {
         sector: (i32, i32, i32),
         should_render: bool,
     },
-    Generated((i32, i32, i32), BlockList),
+    Generated((i32, i32, i32), BlockList, GenerationCause, u32),
 }
 
-const GENERATE_ORDER: [i32; 7] = [0, -1, 1, -2, 2, 3, -3];
-const RENDER_DIST_AXIS: i32 = 2;
-const NUM_WORKERS: usize = 8;
+type Request = ((i32, i32, i32), GenerationCause);
+
+// Sectors waiting to be generated, shared by the workers.
+// Idle workers block on `ready` until there is work, or the
+// queue is closed because the terrain was dropped.
+struct WorkQueue {
+    pending: Mutex<PendingRequests>,
+    ready: Condvar,
+}
+
+struct PendingRequests {
+    requests: VecDeque<Request>,
+    closed: bool,
+}
+
+impl WorkQueue {
+    fn new() -> WorkQueue {
+        WorkQueue {
+            pending: Mutex::new(PendingRequests {
+                requests: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+    
+    // Queue a request and wake a worker. See `push_request`.
+    fn push(&self, request: Request, player: (i32, i32, i32), max_pending: usize) {
+        push_request(&mut self.pending.lock().unwrap().requests, request, player, max_pending);
+        self.ready.notify_one();
+    }
+    
+    fn clear(&self) {
+        self.pending.lock().unwrap().requests.clear();
+    }
+    
+    // Wake every worker and make them stop.
+    fn close(&self) {
+        self.pending.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+    
+    // Block until there is a request to work on.
+    // Returns `None` once the queue is closed.
+    fn pop(&self) -> Option<Request> {
+        let mut pending = self.pending.lock().unwrap();
+        
+        loop {
+            if pending.closed {
+                return None;
+            }
+            
+            if let Some(request) = pending.requests.pop_front() {
+                return Some(request);
+            }
+            
+            pending = self.ready.wait(pending).unwrap();
+        }
+    }
+}
 
 struct TerrainGenThread {
     shared_info: SharedInfo,
+    gen: WorldGen,
+    workers: usize,
     nearby_tx: Sender<Nearby>,
-    needed_rx: Receiver<(i32, i32, i32)>,
+    needed_rx: Receiver<Request>,
 }
 
 impl TerrainGenThread {
     fn new(shared_info: SharedInfo,
+           gen: WorldGen,
+           workers: usize,
            nearby_tx: Sender<Nearby>,
-           needed_rx: Receiver<(i32, i32, i32)>) -> TerrainGenThread {
+           needed_rx: Receiver<Request>) -> TerrainGenThread {
         TerrainGenThread {
             shared_info,
+            gen,
+            workers,
             nearby_tx,
             needed_rx,
         }
     }
     
+    // Start the workers, and the thread that finds the sectors
+    // for them to generate. Every thread stops on its own once
+    // the `Terrain` is dropped.
     fn spawn(self) {
-        let gen = WorldGen::new();
-        let queue = Arc::new(Mutex::new(VecDeque::new()));
-        let nearby_tx = self.nearby_tx.clone();
+        let queue = Arc::new(WorkQueue::new());
         
-        let queue1 = queue.clone();
-        thread::spawn(move || {
-            loop {
-                let info = self.shared_info.lock().unwrap();
-                let player_pos = info.player_pos.clone();
-                //println!("{:?}", player_pos);
-                mem::drop(info);
-                
-                let sector = sector_at(&player_pos);
-                //println!("{:?}", sector);
-                
-                for dx in &GENERATE_ORDER {
-                    for dy in -3..1 {
-                        for dz in &GENERATE_ORDER {
-                            let sector = (sector.0 + dx,
-                                          sector.1 + dy,
-                                          sector.2 + dz);
-                            
-                            let should_render = dx.abs() <= RENDER_DIST_AXIS &&
-                                                dy.abs() <= 1 &&
-                                                dz.abs() <= RENDER_DIST_AXIS;
-                            
-                            if self.nearby_tx.send(Nearby::Query { sector, should_render }).is_err() {
-                                return;
-                            }
-                            
-                            //println!("should_render: {}", should_render);
-                            
-                            /*
-                            if dx.abs() <= RENDER_DIST_AXIS && dz.abs() <= RENDER_DIST_AXIS {
-                                
-                            } else {
-                                println!("won't render {:?}", sector);
-                            }
-                            */
-                        }
-                    }
-                }
-                
-                //
-                
-                while let Ok(needed) = self.needed_rx.try_recv() {
-                    //println!("will generate: {:?}", needed);
-                    
-                    //let list = self.gen.generate(needed);
-                    
-                    //if self.nearby_tx.send(Nearby::Generated(needed, list)).is_err() {
-                    //    return;
-                    //}
-                    queue1.lock().unwrap().push_back(needed);
-                    //println!("push: {:?}", needed);
-                }
-                
-                thread::sleep(Duration::from_secs(4));
-                //println!("tick");
-            }
-        });
-        
-        for _ in 0..NUM_WORKERS {
-            let gen = gen.clone();
+        for _ in 0..self.workers {
+            let gen = self.gen.clone();
             let queue = queue.clone();
-            let nearby_tx = nearby_tx.clone();
+            let nearby_tx = self.nearby_tx.clone();
+            let shared_info = self.shared_info.clone();
             
             thread::spawn(move || {
-                loop {
-                    let item = queue.lock().unwrap().pop_front();
-                    //println!("size: {} ({})", q.len(), i);
-                    //mem::drop(q);
+                while let Some((coords, cause)) = queue.pop() {
+                    let (epoch, region_dir) = {
+                        let info = shared_info.lock().unwrap();
+                        (info.epoch, info.region_dir.clone())
+                    };
                     
-                    if let Some(coords) = item {
-                        let block_list = gen.generate(coords);
-                        
-                        if nearby_tx.send(Nearby::Generated(coords, block_list)).is_err() {
-                            return;
-                        }
-                    }
+                    // Saved sectors are loaded rather than generated.
+                    let saved = region_dir.and_then(|dir| region::load(&dir, coords));
+                    let (block_list, cause) = match saved {
+                        Some(blocks) => (blocks, GenerationCause::Disk),
+                        None => (gen.generate(coords), cause),
+                    };
                     
-                    thread::sleep(Duration::from_millis(5));
+                    if nearby_tx.send(Nearby::Generated(coords, block_list, cause, epoch)).is_err() {
+                        return;
+                    }
                 }
             });
         }
+        
+        thread::spawn(move || {
+            self.scan(&queue);
+            queue.close();
+        });
+    }
+    
+    // Repeatedly ask the main thread which sectors near the
+    // player are needed, and queue them for the workers.
+    // Returns once the main thread hangs up.
+    fn scan(&self, queue: &WorkQueue) {
+        let mut last_epoch = 0;
+        
+        loop {
+            let info = self.shared_info.lock().unwrap();
+            let sector = info.player_sector;
+            let render_radius = info.render_radius;
+            let generate_radius = info.generate_radius;
+            let epoch = info.epoch;
+            let max_pending = info.max_pending;
+            let scan_interval = info.scan_interval;
+            //println!("{:?}", sector);
+            mem::drop(info);
+            
+            // The world was cleared, so the queued requests are stale.
+            if epoch != last_epoch {
+                queue.clear();
+                last_epoch = epoch;
+            }
+            
+            for (sector, should_render) in nearby_sectors(sector, render_radius, generate_radius) {
+                if self.nearby_tx.send(Nearby::Query { sector, should_render }).is_err() {
+                    return;
+                }
+                
+                //println!("should_render: {}", should_render);
+            }
+            
+            // Queue the answers as they arrive until the next scan,
+            // so that workers start on them right away.
+            let deadline = Instant::now() + scan_interval;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                
+                match self.needed_rx.recv_timeout(deadline - now) {
+                    Ok(needed) => {
+                        //println!("push: {:?}", needed);
+                        queue.push(needed, sector, max_pending);
+                    },
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            //println!("tick");
+        }
     }
 }
 
-// The nearest sector at a translation.
-fn sector_at(pos: &Translation) -> (i32, i32, i32) {
+// Queue a sector to be generated, unless it already is. When the queue
+// is full, the farthest request from `player` is dropped to make room,
+// or the new one is if it is the farthest.
+fn push_request(queue: &mut VecDeque<Request>,
+                request: Request,
+                player: (i32, i32, i32), max_pending: usize) {
+    if queue.iter().any(|r| r.0 == request.0) {
+        return;
+    }
+    
+    let dist_sq = |s: (i32, i32, i32)| {
+        let (dx, dy, dz) = (s.0 - player.0, s.1 - player.1, s.2 - player.2);
+        dx * dx + dy * dy + dz * dz
+    };
+    
+    if queue.len() >= max_pending {
+        let farthest = queue.iter()
+                            .enumerate()
+                            .max_by_key(|&(_, r)| dist_sq(r.0))
+                            .map(|(i, r)| (i, dist_sq(r.0)));
+        
+        match farthest {
+            Some((i, d)) if d > dist_sq(request.0) => { queue.remove(i); },
+            _ => return,
+        }
+    }
+    
+    queue.push_back(request);
+}
+
+/// Get the sector containing a translation, relative to the
+/// render origin. Each component is first rounded to the nearest
+/// block, then divided by `SECTOR_SIZE` rounding toward negative
+/// infinity, so `-0.6` is in sector `-1` while `-0.4` is in sector `0`,
+/// and `31.6` is already in sector `1`.
+pub fn sector_at(pos: &Translation) -> (i32, i32, i32) {
     ((pos.x.round() / SECTOR_SIZE as f32).floor() as i32,
      (pos.y.round() / SECTOR_SIZE as f32).floor() as i32,
      (pos.z.round() / SECTOR_SIZE as f32).floor() as i32)
 }
 
+// Record the sector the camera is in, returning whether it moved
+// there from another one. The first sector is not a crossing.
+fn enter_sector(current: &mut Option<(i32, i32, i32)>, sector: (i32, i32, i32)) -> bool {
+    let crossed = current.map_or(false, |s| s != sector);
+    *current = Some(sector);
+    
+    crossed
+}
+
+// A block followed by the six blocks sharing a face with it,
+// whose visible faces may change when it is edited.
+fn block_and_neighbors(pos: (i32, i32, i32)) -> [(i32, i32, i32); 7] {
+    let (x, y, z) = pos;
+    
+    [(x, y, z), (x, y, z - 1), (x, y, z + 1),
+     (x, y + 1, z), (x, y - 1, z),
+     (x - 1, y, z), (x + 1, y, z)]
+}
+
+// Split absolute world block coordinates into the owning
+// sector and the position within that sector.
+fn split_world_coords(pos: (i32, i32, i32)) -> ((i32, i32, i32), SectorSpaceCoords) {
+    let size = SECTOR_SIZE as i32;
+    
+    ((pos.0.div_euclid(size), pos.1.div_euclid(size), pos.2.div_euclid(size)),
+     SectorSpaceCoords::new(pos.0.rem_euclid(size) as u8,
+                            pos.1.rem_euclid(size) as u8,
+                            pos.2.rem_euclid(size) as u8))
+}
+
 const SECTOR_SIZE_F: f32 = SECTOR_SIZE as f32;
 const SECTOR_SIZE_F_2: f32 = SECTOR_SIZE_F / 2.;
 
 fn sector_visible(frustum: &Frustum, pos: (i32, i32, i32)) -> bool {
     // Convert sector coords to world space.
-    let pos = (pos.0 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2,
-               pos.1 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2,
-               pos.2 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2);
+    let min = [pos.0 as f32 * SECTOR_SIZE_F,
+               pos.1 as f32 * SECTOR_SIZE_F,
+               pos.2 as f32 * SECTOR_SIZE_F];
+    let max = [min[0] + SECTOR_SIZE_F,
+               min[1] + SECTOR_SIZE_F,
+               min[2] + SECTOR_SIZE_F];
     
-    //println!("pos: {:?}", pos);
+    //println!("min: {:?}", min);
     //true
     
-    for i in frustum.planes() {
-        //println!("plane: {:?}", i);
+    frustum.intersects_aabb(min, max)
+}
+
+// Choose the sectors to draw, from the key of every loaded sector
+// and whether it has a mesh. Sectors outside `frustum`, if given,
+// are culled. `order` is filled with the squared distance from
+// `eye` and the key of each sector to draw, and the counts of the
+// sectors considered are returned. Nothing needs OpenGL, so the
+// choice can be checked on its own.
+fn plan_draw<I>(sectors: I, origin: (i32, i32, i32), frustum: Option<&Frustum>,
+                eye: Vec3, order: &mut Vec<(f32, (i32, i32, i32))>) -> RenderStats
+    where I: Iterator<Item = ((i32, i32, i32), bool)> {
+    let mut stats = RenderStats::default();
+    order.clear();
+    
+    for (pos, meshed) in sectors {
+        stats.sectors_total += 1;
         
-        let d = i.a * pos.0 + i.b * pos.1 + i.c * pos.2 + i.d;
+        if !meshed {
+            stats.sectors_empty += 1;
+            continue;
+        }
         
-        if d <= -SECTOR_SIZE_F {
-            return false;
+        let local = (pos.0 - origin.0,
+                     pos.1 - origin.1,
+                     pos.2 - origin.2);
+        
+        if frustum.map_or(false, |f| !sector_visible(f, local)) {
+            stats.sectors_culled += 1;
+            continue;
         }
+        
+        order.push(((sector_center(local) - eye).length_squared(), pos));
     }
     
+    stats
+}
+
+// Sort sectors chosen by `plan_draw` nearest first, so the depth
+// test rejects the hidden fragments of farther sectors early.
+fn sort_nearest_first(order: &mut [(f32, (i32, i32, i32))]) {
+    order.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+}
+
+// The sectors the generation thread asks about around `center`,
+// nearest first along each axis, and whether each should be
+// rendered. Only the sectors at and just below the camera's
+// level are generated.
+fn nearby_sectors(center: (i32, i32, i32), render_radius: i32,
+                  generate_radius: i32) -> Vec<((i32, i32, i32), bool)> {
+    // Nearest first: 0, -1, 1, -2, 2, ...
+    let mut order = vec![0];
+    for r in 1..generate_radius + 1 {
+        order.push(-r);
+        order.push(r);
+    }
+    
+    let mut nearby = Vec::new();
+    for dx in &order {
+        for dy in -3..1 {
+            for dz in &order {
+                let sector = (center.0 + dx,
+                              center.1 + dy,
+                              center.2 + dz);
+                
+                let should_render = dx.abs() <= render_radius &&
+                                    dy.abs() <= 1 &&
+                                    dz.abs() <= render_radius;
+                
+                nearby.push((sector, should_render));
+                
+                /*
+                if dx.abs() <= RENDER_DIST_AXIS && dz.abs() <= RENDER_DIST_AXIS {
+                
+                } else {
+                    println!("won't render {:?}", sector);
+                }
+                */
+            }
+        }
+    }
+    
+    nearby
+}
+
+// Decide what `update` keeps of the sector at `k` while the camera
+// is in `center`: `None` if it is dropped, otherwise whether its
+// model is kept too.
+fn retain_sector(k: (i32, i32, i32), center: (i32, i32, i32),
+                 render_radius: i32, generate_radius: i32) -> Option<bool> {
+    // Keep one sector past the generation radius, so that
+    // sectors do not thrash when the camera is on a boundary.
+    let keep = (generate_radius + 1) as f32;
+    let render = (render_radius + 1) as f32;
+    
+    // The offset from the camera's sector, in sectors.
+    let d = (sector_center(k) - sector_center(center)) * (1. / SECTOR_SIZE_F);
+    
+    if d.x.abs() > keep || d.z.abs() > keep || d.y < -4. || d.y > 1. {
+        return None;
+    }
+    
+    // Beyond the render radius, only the blocks are kept.
+    Some(d.x.abs() <= render && d.z.abs() <= render)
+}
+
+// The center of a sector, relative to the render origin.
+fn sector_center(pos: (i32, i32, i32)) -> Vec3 {
+    Vec3::new(pos.0 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2,
+              pos.1 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2,
+              pos.2 as f32 * SECTOR_SIZE_F + SECTOR_SIZE_F_2)
+}
+
+// How far the edge of the drawn terrain is from the camera,
+// in blocks, for a render radius.
+fn fog_distance_for(render_radius: i32) -> f32 {
+    (render_radius as f32 + 0.5) * SECTOR_SIZE_F
+}
+
+// The time since `begin`, in seconds.
+fn seconds_since(begin: Instant) -> f64 {
+    let duration = Instant::now() - begin;
+    
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+// Implements `Terrain::collide`, finding blocks with `block_at`,
+// which returns `None` where the terrain is not ready.
+fn collide<F>(block_at: &F, translation: &mut Translation, step_height: f32) -> CollisionResult
+    where F: Fn(&Translation) -> Option<Block> {
+    let mut result = CollisionResult::default();
+    
+    // Back, front, above, below, left and right.
+    let sides = [(2, -1.), (2, 1.), (1, 1.), (1, -1.), (0, -1.), (0, 1.)];
+    
+    for &(axis, dir) in &sides {
+        let (contact, moved) = collide_axis(block_at, translation, axis, dir, step_height);
+        
+        result.corrected |= moved;
+        match (axis, dir < 0.) {
+            (1, true) => result.grounded |= contact,
+            (1, false) => result.hit_ceiling |= contact,
+            _ => result.hit_wall |= contact,
+        }
+    }
+    
+    result
+}
+
+// Resolve a collision with the block beside the translation along
+// `axis` (`0` for X, `1` for Y, `2` for Z), on the side `dir`, which
+// is `-1` or `1`. Sideways obstacles may be stepped onto. Returns
+// whether the block stopped the translation, and whether the
+// translation was moved. Standing exactly on the ground counts
+// as being stopped by it.
+fn collide_axis<F>(block_at: &F, translation: &mut Translation, axis: usize,
+                   dir: f32, step_height: f32) -> (bool, bool)
+    where F: Fn(&Translation) -> Option<Block> {
+    let mut obstacle = translation.clone();
+    {
+        let c = component_mut(&mut obstacle, axis);
+        *c = c.round() + dir;
+    }
+    
+    let solid = match block_at(&obstacle) {
+        Some(b) => !b.is_air(),
+        None => false,
+    };
+    if !solid {
+        return (false, false);
+    }
+    
+    let margin = *component_mut(&mut obstacle, axis) - dir * (1. + COLLIDE_PADDING);
+    
+    // How far the translation reaches past the margin.
+    let depth = (*component_mut(translation, axis) - margin) * dir;
+    
+    let slack = if axis == 1 && dir < 0. { STEP_EPSILON } else { 0. };
+    if depth <= -slack {
+        return (false, false);
+    }
+    
+    if axis != 1 && step_up(block_at, translation, &obstacle, step_height) {
+        return (false, true);
+    }
+    
+    if depth > 0. {
+        *component_mut(translation, axis) = margin;
+    }
+    
+    (true, depth > 0.)
+}
+
+// Lift the translation onto the ledge at `obstacle` if it is
+// low enough and has clearance above. Returns whether it did.
+fn step_up<F>(block_at: &F, translation: &mut Translation, obstacle: &Translation,
+              step_height: f32) -> bool
+    where F: Fn(&Translation) -> Option<Block> {
+    let top = obstacle.y.round() + 1.;
+    let rise = top + COLLIDE_PADDING - translation.y;
+    
+    if rise > step_height + STEP_EPSILON {
+        return false;
+    }
+    
+    let clear = |t: Translation| {
+        match block_at(&t) {
+            Some(b) => b.is_air(),
+            None => true,
+        }
+    };
+    
+    if !clear(Translation::new(obstacle.x, top, obstacle.z)) ||
+       !clear(Translation::new(translation.x, top, translation.z)) {
+        return false;
+    }
+    
+    translation.y = top + COLLIDE_PADDING;
     true
 }
+
+// The X, Y or Z component of a translation, by axis number.
+fn component_mut(t: &mut Translation, axis: usize) -> &mut f32 {
+    match axis {
+        0 => &mut t.x,
+        1 => &mut t.y,
+        _ => &mut t.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maths::Projection;
+    
+    #[test]
+    fn edit_dirties_block_and_face_neighbors() {
+        let dirty = block_and_neighbors((5, 6, 7));
+        
+        assert_eq!(dirty[0], (5, 6, 7));
+        for &(x, y, z) in &dirty[1..] {
+            let dist = (x - 5).abs() + (y - 6).abs() + (z - 7).abs();
+            assert_eq!(dist, 1);
+        }
+        
+        let distinct: HashSet<_> = dirty.iter().collect();
+        assert_eq!(distinct.len(), 7);
+    }
+    
+    #[test]
+    fn edit_at_sector_corner_touches_bordering_sectors() {
+        let touched: HashSet<_> = block_and_neighbors((0, 0, 0)).iter()
+                                                                .map(|&p| split_world_coords(p).0)
+                                                                .collect();
+        let expected: HashSet<_> = [(0, 0, 0), (-1, 0, 0), (0, -1, 0), (0, 0, -1)]
+                                   .iter().cloned().collect();
+        
+        assert_eq!(touched, expected);
+    }
+    
+    #[test]
+    fn sector_at_rounds_negative_positions_down() {
+        let at = |x: f32| sector_at(&Translation::new(x, 0., 0.)).0;
+        
+        assert_eq!(at(-0.4), 0);
+        assert_eq!(at(-0.5), -1);
+        assert_eq!(at(-0.6), -1);
+        assert_eq!(at(-32.), -1);
+        assert_eq!(at(-32.6), -2);
+        assert_eq!(at(31.4), 0);
+        assert_eq!(at(31.6), 1);
+    }
+    
+    #[test]
+    fn crossing_sector_boundary_is_reported_once() {
+        let mut current = None;
+        let mut crossings = Vec::new();
+        
+        // Walk across the boundary at x = 32 in small steps.
+        for step in 0..80 {
+            let x = 20. + step as f32 * 0.25;
+            if enter_sector(&mut current, sector_at(&Translation::new(x, 5., 5.))) {
+                crossings.push(x);
+            }
+        }
+        
+        assert_eq!(crossings, vec![31.5]);
+        assert_eq!(current, Some((1, 0, 0)));
+        
+        // Coming back crosses again.
+        assert!(enter_sector(&mut current, (0, 0, 0)));
+        assert!(!enter_sector(&mut current, (0, 0, 0)));
+    }
+    
+    #[test]
+    fn first_sector_is_not_a_crossing() {
+        let mut current = None;
+        
+        assert!(!enter_sector(&mut current, (3, -1, 2)));
+        assert_eq!(current, Some((3, -1, 2)));
+    }
+    
+    #[test]
+    fn nearby_sectors_cover_generate_radius_once() {
+        let center = (4, -1, -7);
+        
+        for &radius in &[0, 1, 3] {
+            let (render_radius, generate_radius) = (radius, radius + 2);
+            let nearby = nearby_sectors(center, render_radius, generate_radius);
+            
+            let side = (2 * generate_radius + 1) as usize;
+            assert_eq!(nearby.len(), side * side * 4);
+            
+            let unique: HashSet<_> = nearby.iter().map(|n| n.0).collect();
+            assert_eq!(unique.len(), nearby.len());
+            
+            for &(sector, should_render) in &nearby {
+                let (dx, dy, dz) = (sector.0 - center.0, sector.1 - center.1, sector.2 - center.2);
+                assert!(dx.abs() <= generate_radius && dz.abs() <= generate_radius);
+                assert!(dy >= -3 && dy <= 0);
+                
+                let in_render = dx.abs() <= render_radius && dz.abs() <= render_radius;
+                assert_eq!(should_render, in_render && dy >= -1, "{:?}", sector);
+                
+                // `update` keeps every sector it asked for, and
+                // the model of every one it asked to render.
+                let retained = retain_sector(sector, center, render_radius, generate_radius);
+                assert!(retained.is_some(), "{:?}", sector);
+                if should_render {
+                    assert_eq!(retained, Some(true), "{:?}", sector);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn retain_sector_keeps_one_past_generate_radius() {
+        let center = (0, 0, 0);
+        let retain = |dx: i32| retain_sector((dx, 0, 0), center, 1, 3);
+        
+        assert_eq!(retain(2), Some(true));
+        assert_eq!(retain(3), Some(false));
+        assert_eq!(retain(4), Some(false));
+        assert_eq!(retain(5), None);
+        assert_eq!(retain_sector((0, 2, 0), center, 1, 3), None);
+        assert_eq!(retain_sector((0, -5, 0), center, 1, 3), None);
+    }
+        
+        // A block lookup for `collide`, with solid blocks at `solid`
+    // and for `y <= 0`, and air elsewhere.
+    fn block_at(solid: &[(i32, i32, i32)], t: &Translation) -> Option<Block> {
+        let pos = (t.x.round() as i32, t.y.round() as i32, t.z.round() as i32);
+        
+        if pos.1 <= 0 || solid.contains(&pos) {
+            Some(Block::Limestone)
+        } else {
+            Some(Block::Air)
+        }
+    }
+    
+    #[test]
+    fn steps_onto_one_block_ledge() {
+        let solid = [(1, 1, 0)];
+        let mut t = Translation::new(0., 1.3, 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&solid, p), &mut t, DEFAULT_STEP_HEIGHT);
+        
+        assert!(result.corrected);
+        assert!(!result.hit_wall);
+        assert_eq!(t.x, 0.);
+        assert!((t.y - 2.3).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn two_block_wall_blocks() {
+        let solid = [(1, 1, 0), (1, 2, 0)];
+        let mut t = Translation::new(0., 1.3, 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&solid, p), &mut t, DEFAULT_STEP_HEIGHT);
+        
+        assert!(result.corrected);
+        assert!(result.hit_wall);
+        assert!((t.x + COLLIDE_PADDING).abs() < 1e-5);
+        assert!((t.y - 1.3).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn zero_step_height_disables_stepping() {
+        let solid = [(1, 1, 0)];
+        let mut t = Translation::new(0., 1.3, 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&solid, p), &mut t, 0.);
+        
+        assert!(result.hit_wall);
+        assert!((t.y - 1.3).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn standing_on_ground_is_grounded_but_not_corrected() {
+        let mut t = Translation::new(0., 1.3, 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&[], p), &mut t, DEFAULT_STEP_HEIGHT);
+        
+        assert_eq!(result, CollisionResult { grounded: true, ..CollisionResult::default() });
+    }
+    
+    #[test]
+    fn jumping_into_ceiling_is_pushed_down() {
+        let solid = [(0, 3, 0)];
+        let mut t = Translation::new(0., 2.4, 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&solid, p), &mut t, DEFAULT_STEP_HEIGHT);
+        
+        assert!(result.corrected);
+        assert!(result.hit_ceiling);
+        assert!(!result.grounded);
+        assert!(!result.hit_wall);
+        assert!((t.y - 1.7).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn open_air_touches_nothing() {
+        let mut t = Translation::new(0., 5., 0.);
+        
+        let result = collide(&|p: &Translation| block_at(&[], p), &mut t, DEFAULT_STEP_HEIGHT);
+        
+        assert_eq!(result, CollisionResult::default());
+    }
+    
+    // A camera at the origin, looking along negative Z.
+    fn frustum_toward_negative_z() -> Frustum {
+        Frustum::new(&Projection::new(1., 1., 0.1, 1000.).to_matrix(), &maths::IDENTITY)
+    }
+    
+    #[test]
+    fn frustum_facing_away_culls_every_sector() {
+        let sectors = vec![((0, 0, 2), true), ((1, 0, 3), true), ((-1, 1, 4), true)];
+        let mut order = Vec::new();
+        
+        let stats = plan_draw(sectors.into_iter(), (0, 0, 0), Some(&frustum_toward_negative_z()),
+                              Vec3::new(0., 0., 0.), &mut order);
+        
+        assert_eq!(stats.sectors_total, 3);
+        assert_eq!(stats.sectors_culled, stats.sectors_total);
+        assert!(order.is_empty());
+    }
+    
+    #[test]
+    fn plan_draw_counts_sectors() {
+        // In front, behind, and without a mesh. The origin
+        // moves the first two to `(0, 0, -3)` and `(0, 0, 2)`.
+        let sectors = vec![((5, 0, -3), true), ((5, 0, 2), true), ((5, 0, -4), false)];
+        let mut order = Vec::new();
+        
+        let stats = plan_draw(sectors.clone().into_iter(), (5, 0, 0),
+                              Some(&frustum_toward_negative_z()),
+                              Vec3::new(0., 0., 0.), &mut order);
+        
+        assert_eq!(stats, RenderStats { sectors_total: 3, sectors_drawn: 0,
+                                        sectors_culled: 1, sectors_empty: 1 });
+        assert_eq!(order.iter().map(|o| o.1).collect::<Vec<_>>(), vec![(5, 0, -3)]);
+        
+        let stats = plan_draw(sectors.into_iter(), (5, 0, 0), None,
+                              Vec3::new(0., 0., 0.), &mut order);
+        
+        assert_eq!(stats.sectors_culled, 0);
+        assert_eq!(order.len(), 2);
+    }
+    
+    #[test]
+    fn draw_order_is_nearest_first() {
+        let sectors = vec![((0, 0, -5), true), ((0, 0, -1), true),
+                           ((2, 0, -3), true), ((0, 0, -2), true)];
+        let mut order = Vec::new();
+        
+        plan_draw(sectors.into_iter(), (0, 0, 0), None, Vec3::new(0., 0., 0.), &mut order);
+        sort_nearest_first(&mut order);
+        
+        let keys: Vec<_> = order.iter().map(|o| o.1).collect();
+        assert_eq!(keys, vec![(0, 0, -1), (0, 0, -2), (2, 0, -3), (0, 0, -5)]);
+    }
+}
@@ -0,0 +1,184 @@
+//! A lightweight particle system, used for the debris
+//! of broken blocks.
+
+use luminance::linear::M44;
+use super::{UV, Vertex};
+
+/// The most particles alive at once. Bursts past
+/// this limit are truncated.
+pub const MAX_PARTICLES: usize = 256;
+
+const PARTICLES_PER_BURST: usize = 12;
+const LIFETIME: f32 = 0.8;
+const GRAVITY: f32 = 20.;
+const SPEED: f32 = 3.;
+const HALF_SIZE: f32 = 0.08;
+
+// Particles are lit like the top face of a block,
+// and use no per-block shader effects or animation.
+const FACE: u32 = 2;
+const BLOCK_ID: u32 = 0;
+const ANIM: u32 = 0;
+const BLEND_TILE: u32 = 0;
+const BLEND_WEIGHT: f32 = 0.;
+const LIGHT: [f32; 3] = [0.; 3];
+const SKY_LIGHT: f32 = 1.;
+const OCCLUSION: f32 = 1.;
+
+/// A single particle.
+#[derive(Clone, Debug)]
+struct Particle {
+    pos: [f32; 3],
+    vel: [f32; 3],
+    life: f32,
+    uv: (UV, UV, UV, UV),
+    atlas: usize,
+}
+
+/// A collection of particles, simulated with simple gravity.
+pub struct Particles {
+    particles: Vec<Particle>,
+    seed: u32,
+}
+
+impl Particles {
+    /// Create an empty particle system.
+    pub fn new() -> Particles {
+        Particles {
+            particles: Vec::with_capacity(MAX_PARTICLES),
+            seed: 0x9E37_79B9,
+        }
+    }
+    
+    /// Spawn a burst of particles from the center of the block
+    /// whose corner is at `pos`, textured with pieces of the
+    /// tile `tile` in the terrain atlas `atlas`.
+    pub fn burst(&mut self, pos: [f32; 3], tile: (UV, UV, UV, UV), atlas: usize) {
+        let center = [pos[0] + 0.5, pos[1] + 0.5, pos[2] + 0.5];
+        
+        for _ in 0..PARTICLES_PER_BURST {
+            if self.particles.len() >= MAX_PARTICLES {
+                break;
+            }
+            
+            let vel = [(self.random() - 0.5) * SPEED,
+                       self.random() * SPEED,
+                       (self.random() - 0.5) * SPEED];
+            
+            // Use a random quarter of the tile.
+            let (u0, v0) = ((tile.2)[0], (tile.2)[1]);
+            let (du, dv) = (((tile.0)[0] - u0) / 4., ((tile.0)[1] - v0) / 4.);
+            let (u, v) = (u0 + du * (self.random() * 4.).floor(),
+                          v0 + dv * (self.random() * 4.).floor());
+            let life = LIFETIME * (0.5 + self.random() * 0.5);
+            
+            self.particles.push(Particle {
+                pos: center,
+                vel,
+                life,
+                uv: ([u + du, v + dv], [u + du, v], [u, v], [u, v + dv]),
+                atlas,
+            });
+        }
+    }
+    
+    /// Advance the particles by `dt` seconds, removing expired ones.
+    pub fn tick(&mut self, dt: f32) {
+        for p in &mut self.particles {
+            p.vel[1] -= GRAVITY * dt;
+            
+            p.pos[0] += p.vel[0] * dt;
+            p.pos[1] += p.vel[1] * dt;
+            p.pos[2] += p.vel[2] * dt;
+            
+            p.life -= dt;
+        }
+        
+        self.particles.retain(|p| p.life > 0.);
+    }
+    
+    /// Move every particle by `offset`, such as when
+    /// the render origin of the terrain changes.
+    pub fn shift(&mut self, offset: [f32; 3]) {
+        for p in &mut self.particles {
+            p.pos[0] += offset[0];
+            p.pos[1] += offset[1];
+            p.pos[2] += offset[2];
+        }
+    }
+    
+    /// Determine if there are no living particles.
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+    
+    /// Generate quads facing the camera with the given view matrix,
+    /// for the particles textured from the terrain atlas `atlas`.
+    pub fn vertices(&self, view: &M44, atlas: usize) -> Vec<Vertex> {
+        // The first two rows of the view rotation are the
+        // camera's right and up vectors in world space.
+        let right = [view[0][0] * HALF_SIZE, view[1][0] * HALF_SIZE, view[2][0] * HALF_SIZE];
+        let up = [view[0][1] * HALF_SIZE, view[1][1] * HALF_SIZE, view[2][1] * HALF_SIZE];
+        
+        let mut v = Vec::with_capacity(self.particles.len() * 6);
+        
+        for p in self.particles.iter().filter(|p| p.atlas == atlas) {
+            let corner = |r: f32, u: f32| {
+                [p.pos[0] + right[0] * r + up[0] * u,
+                 p.pos[1] + right[1] * r + up[1] * u,
+                 p.pos[2] + right[2] * r + up[2] * u]
+            };
+            
+            let vtx0 = (corner(1., -1.), p.uv.0, FACE, BLOCK_ID, ANIM, BLEND_TILE,
+                        BLEND_WEIGHT, LIGHT, SKY_LIGHT, OCCLUSION);
+            let vtx1 = (corner(1., 1.), p.uv.1, FACE, BLOCK_ID, ANIM, BLEND_TILE,
+                        BLEND_WEIGHT, LIGHT, SKY_LIGHT, OCCLUSION);
+            let vtx2 = (corner(-1., 1.), p.uv.2, FACE, BLOCK_ID, ANIM, BLEND_TILE,
+                        BLEND_WEIGHT, LIGHT, SKY_LIGHT, OCCLUSION);
+            let vtx3 = (corner(-1., -1.), p.uv.3, FACE, BLOCK_ID, ANIM, BLEND_TILE,
+                        BLEND_WEIGHT, LIGHT, SKY_LIGHT, OCCLUSION);
+            
+            // End both triangles on the top left corner, which
+            // the shader wraps the texture coordinates around.
+            v.push(vtx0);
+            v.push(vtx1);
+            v.push(vtx2);
+            
+            v.push(vtx3);
+            v.push(vtx0);
+            v.push(vtx2);
+        }
+        
+        v
+    }
+    
+    // A xorshift generator returning values in [0, 1).
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        
+        (self.seed >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn shift_moves_burst_by_offset() {
+        let tile = ([1., 1.], [1., 0.], [0., 0.], [0., 1.]);
+        let mut particles = Particles::new();
+        particles.burst([10., 20., 30.], tile, 0);
+        particles.tick(0.1);
+        
+        let before: Vec<_> = particles.particles.iter().map(|p| p.pos).collect();
+        particles.shift([-512., 0., 512.]);
+        
+        assert_eq!(before.len(), PARTICLES_PER_BURST);
+        for (b, p) in before.iter().zip(&particles.particles) {
+            assert_eq!(p.pos, [b[0] - 512., b[1], b[2] + 512.]);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Storage of sectors on disk, so that they are loaded
+//! instead of generated again on the next launch.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use super::light;
+use super::voxel::BlockList;
+
+// The file holding a sector, in a region directory.
+fn sector_path(dir: &Path, coords: (i32, i32, i32)) -> PathBuf {
+    dir.join(format!("sector_{}_{}_{}.bin", coords.0, coords.1, coords.2))
+}
+
+/// Write a sector to the region directory `dir`, creating
+/// the directory if needed. See `BlockList::serialize`.
+pub fn save(dir: &Path, coords: (i32, i32, i32), blocks: &BlockList) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    
+    File::create(sector_path(dir, coords))?.write_all(&blocks.serialize())
+}
+
+/// Read a sector from the region directory `dir`, if it was saved,
+/// and light it like a generated one. A file that can not be read
+/// or decoded is reported and ignored, so the sector is generated
+/// again.
+pub fn load(dir: &Path, coords: (i32, i32, i32)) -> Option<BlockList> {
+    let mut file = File::open(sector_path(dir, coords)).ok()?;
+    
+    let mut data = Vec::new();
+    if let Err(e) = file.read_to_end(&mut data) {
+        eprintln!("failed to read sector {:?}: {}", coords, e);
+        return None;
+    }
+    
+    match BlockList::deserialize(&data) {
+        Ok(mut blocks) => {
+            light::propagate_sky_light(&mut blocks, light::open_to_sky(coords));
+            Some(blocks)
+        },
+        Err(e) => {
+            eprintln!("failed to load sector {:?}: {:?}", coords, e);
+            None
+        },
+    }
+}
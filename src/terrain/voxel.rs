@@ -1,8 +1,11 @@
 //! A module for managing the voxels in the world.
 
 use std::{iter, slice};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use luminance::tess::{Mode, Tess, TessVertices};
-use super::{mesh_gen, Vertex, SECTOR_SIZE};
+use super::{mesh_gen, GenerationCause, Vertex, SECTOR_SIZE};
+use super::light::{LightColor, LightMap};
 use maths::Translation;
 use model::Model;
 use resources::Resources;
@@ -13,18 +16,81 @@ use resources::Resources;
 //  ([ 0.5, -0.5, 0.0], [1.0, 1.0]),
 //];
 
-/// A block in the world.
-#[derive(Clone, Copy, Debug)]
+/// A block in the world. The discriminant is the block id, see `id`.
+///
+/// Ids are stored in saved sectors and index the atlas tiles, so
+/// existing blocks must keep their ids. `Air` is always `0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
 pub enum Block {
-    Air,
-    Limestone,
-    Loam,
-    Grass,
-    Tree,
-    Leaves,
+    Air = 0,
+    Limestone = 1,
+    Loam = 2,
+    Grass = 3,
+    Tree = 4,
+    Leaves = 5,
+    Water = 6,
+    Bedrock = 7,
+    CoalOre = 8,
+    IronOre = 9,
+    Sand = 10,
 }
 
+// Every block, in id order.
+const ALL_BLOCKS: [Block; 11] = [
+    Block::Air,
+    Block::Limestone,
+    Block::Loam,
+    Block::Grass,
+    Block::Tree,
+    Block::Leaves,
+    Block::Water,
+    Block::Bedrock,
+    Block::CoalOre,
+    Block::IronOre,
+    Block::Sand,
+];
+
 impl Block {
+    /// Get every type of block, in id order.
+    pub fn all() -> &'static [Block] {
+        &ALL_BLOCKS
+    }
+    
+    /// Get the lowercase name of the block, such as `"limestone"`.
+    /// Names are unique, so they can be used to look blocks up.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Block::Air => "air",
+            Block::Limestone => "limestone",
+            Block::Loam => "loam",
+            Block::Grass => "grass",
+            Block::Tree => "tree",
+            Block::Leaves => "leaves",
+            Block::Water => "water",
+            Block::Bedrock => "bedrock",
+            Block::CoalOre => "coal_ore",
+            Block::IronOre => "iron_ore",
+            Block::Sand => "sand",
+        }
+    }
+    
+    /// Find the block with this name, as returned by `name`.
+    pub fn from_name(name: &str) -> Option<Block> {
+        ALL_BLOCKS.iter().find(|b| b.name() == name).cloned()
+    }
+    
+    /// Get the id of the block. The atlas tile of a block
+    /// is its id less one, since air has no tile.
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+    
+    /// Find the block with this id, as returned by `id`.
+    pub fn from_id(id: u8) -> Option<Block> {
+        ALL_BLOCKS.iter().find(|b| b.id() == id).cloned()
+    }
+    
     /// Determine if the block is air.
     pub fn is_air(&self) -> bool {
         match *self {
@@ -37,6 +103,97 @@ impl Block {
     pub fn needs_rendering(&self) -> bool {
         !self.is_air()
     }
+    
+    /// Determine if the block is see-through. Transparent blocks
+    /// are drawn in a second, blended pass after opaque ones.
+    pub fn is_transparent(&self) -> bool {
+        match *self {
+            Block::Water => true,
+            _ => false,
+        }
+    }
+    
+    /// Determine if this block hides the face of `other` that
+    /// touches it. Transparent blocks only hide their own kind,
+    /// so that the ground under water is still drawn.
+    pub fn hides(&self, other: &Block) -> bool {
+        self.needs_rendering() && (!self.is_transparent() || self == other)
+    }
+    
+    /// Get the index of the terrain atlas holding the block's tiles,
+    /// in `resources::TERRAIN_ATLASES`. Within an atlas, the tiles
+    /// are found by `resources::AtlasLayout`.
+    ///
+    /// Transparent blocks must be in atlas `0`, since their faces
+    /// are sorted together and drawn as one batch.
+    pub fn atlas(&self) -> usize {
+        // Every block still fits in the first atlas.
+        0
+    }
+    
+    /// Determine if the block's texture blends into a different
+    /// neighboring ground block, when edge blending is enabled.
+    pub fn blends(&self) -> bool {
+        match *self {
+            Block::Limestone | Block::Loam | Block::Grass | Block::Sand => true,
+            _ => false,
+        }
+    }
+    
+    /// Get the texture animation of the block, if it has one.
+    pub fn animation(&self) -> Option<Animation> {
+        match *self {
+            Block::Water => Some(Animation::new(28, 4, 0.25)),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for Block {
+    type Error = u8;
+    
+    /// Find the block with this id. Unknown ids are returned as the error.
+    fn try_from(id: u8) -> Result<Block, u8> {
+        Block::from_id(id).ok_or(id)
+    }
+}
+
+/// A texture animation, cycling through a run of atlas tiles.
+///
+/// The frames must be consecutive tiles along an atlas row. The
+/// first tiles of the atlas are used by the blocks, one each in
+/// id order, so animation frames are placed at the end of the row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    /// The atlas tile index of the first frame.
+    pub first_tile: u32,
+    
+    /// The number of frames, from `1` to `255`.
+    pub frames: u32,
+    
+    /// How long each frame is shown, in seconds.
+    pub frame_time: f32,
+}
+
+impl Animation {
+    /// Create an animation of `frames` tiles, starting
+    /// at `first_tile`, showing each for `frame_time` seconds.
+    pub fn new(first_tile: u32, frames: u32, frame_time: f32) -> Animation {
+        Animation {
+            first_tile,
+            frames: frames.max(1).min(255),
+            frame_time,
+        }
+    }
+    
+    /// Pack the animation into a vertex attribute for the shader.
+    /// The frame count is in the low 8 bits, and the frame time
+    /// in milliseconds is in the rest. Static blocks use `0`.
+    pub fn pack(&self) -> u32 {
+        let millis = (self.frame_time * 1000.).max(1.) as u32;
+        
+        self.frames | (millis << 8)
+    }
 }
 
 // The length of an array of blocks for a sector.
@@ -54,16 +211,26 @@ impl SectorSpaceCoords {
     /// Create a new coordinate triple.
     /// # Panics
     /// Panics if any component is >= `SECTOR_SIZE`.
+    /// See `try_new` for a version that does not.
     pub fn new(x: u8, y: u8, z: u8) -> SectorSpaceCoords {
+        match Self::try_new(x, y, z) {
+            Some(coords) => coords,
+            None => panic!("SectorSpaceCoords out of range"),
+        }
+    }
+    
+    /// Create a new coordinate triple, or return `None`
+    /// if any component is >= `SECTOR_SIZE`.
+    pub fn try_new(x: u8, y: u8, z: u8) -> Option<SectorSpaceCoords> {
         if x as usize >= SECTOR_SIZE || y as usize >= SECTOR_SIZE || z as usize >= SECTOR_SIZE {
-            panic!("SectorSpaceCoords out of range");
+            return None;
         }
         
-        SectorSpaceCoords {
+        Some(SectorSpaceCoords {
             x,
             y,
             z,
-        }
+        })
     }
     
     /// If possible, create the coord for the block
@@ -131,50 +298,202 @@ impl SectorSpaceCoords {
     pub fn z(&self) -> u8 { self.z }
 }
 
-/// The array structure of blocks in a `Sector`.
-pub struct BlockList([Block; SECTOR_LEN]);
+/// The array structure of blocks in a `Sector`, along
+/// with the sky light level at each block.
+///
+/// The number of blocks that are not air is kept up to date as
+/// blocks are set, so checking for an empty sector is cheap.
+pub struct BlockList {
+    blocks: [Block; SECTOR_LEN],
+    sky_light: [u8; SECTOR_LEN],
+    solid_count: usize,
+}
+
+impl Clone for BlockList {
+    fn clone(&self) -> BlockList {
+        BlockList {
+            blocks: self.blocks,
+            sky_light: self.sky_light,
+            solid_count: self.solid_count,
+        }
+    }
+}
 
 impl BlockList {
     /// Create a new `BlockList`, consuming the array
-    /// of `Block`s.
+    /// of `Block`s. Every block starts without sky light.
     pub fn new(blocks: [Block; SECTOR_LEN]) -> BlockList {
-        BlockList(blocks)
+        let solid_count = blocks.iter().filter(|b| !b.is_air()).count();
+        
+        BlockList {
+            blocks,
+            sky_light: [0; SECTOR_LEN],
+            solid_count,
+        }
     }
     
     /// Create a new `BlockList` fulled with air.
     pub fn new_air() -> BlockList {
-        BlockList([Block::Air; SECTOR_LEN])
+        Self::new([Block::Air; SECTOR_LEN])
     }
 
     /// Look at the block at a specific position in sector coords.
     pub fn get(&self, pos: SectorSpaceCoords) -> &Block {
-        &self.0[Self::index(pos)]
+        &self.blocks[Self::index(pos)]
     }
     
     /// Set a block at a specific position in sector coords.
+    /// The sky light is not updated, see `light::propagate_sky_light`.
     pub fn set(&mut self, pos: SectorSpaceCoords, block: Block) {
-        self.0[Self::index(pos)] = block;
+        let b = &mut self.blocks[Self::index(pos)];
+        replace_counted(b, block, &mut self.solid_count);
     }
     
-    /// Determine if all blocks in the `BlockList` are air.
+    /// Set every block to `block`.
+    pub fn fill(&mut self, block: Block) {
+        for b in self.blocks.iter_mut() {
+            *b = block;
+        }
+        
+        self.solid_count = if block.is_air() { 0 } else { SECTOR_LEN };
+    }
+    
+    /// Set every block in the box between the corners `a` and `b`,
+    /// inclusive, to `block`. The corners may be in any order, and
+    /// are given as sector space positions that may lie outside the
+    /// sector. The part of the box outside the sector is skipped.
+    pub fn fill_box(&mut self, a: (i32, i32, i32), b: (i32, i32, i32), block: Block) {
+        let last = SECTOR_SIZE as i32 - 1;
+        let lo = |a: i32, b: i32| a.min(b).max(0);
+        let hi = |a: i32, b: i32| a.max(b).min(last);
+        
+        let (x0, x1) = (lo(a.0, b.0), hi(a.0, b.0));
+        let (y0, y1) = (lo(a.1, b.1), hi(a.1, b.1));
+        let (z0, z1) = (lo(a.2, b.2), hi(a.2, b.2));
+        
+        // The box is entirely outside the sector.
+        if x0 > x1 || y0 > y1 || z0 > z1 {
+            return;
+        }
+        
+        // Runs along x are contiguous.
+        for z in z0..z1 + 1 {
+            for y in y0..y1 + 1 {
+                let row = y as usize * SECTOR_SIZE + z as usize * SECTOR_SIZE * SECTOR_SIZE;
+                
+                for b in &mut self.blocks[row + x0 as usize..row + x1 as usize + 1] {
+                    replace_counted(b, block, &mut self.solid_count);
+                }
+            }
+        }
+    }
+    
+    /// Get the sky light level at a position in sector coords,
+    /// from `0` to `MAX_LIGHT`.
+    pub fn light_at(&self, pos: SectorSpaceCoords) -> u8 {
+        self.sky_light[Self::index(pos)]
+    }
+    
+    /// Set the sky light level at a position in sector coords.
+    pub fn set_light(&mut self, pos: SectorSpaceCoords, level: u8) {
+        self.sky_light[Self::index(pos)] = level;
+    }
+    
+    /// Determine if any block in the `BlockList` is not air.
     pub fn needs_rendering(&self) -> bool {
-        for i in self.0.iter() {
-            if i.needs_rendering() {
-                return true;
+        self.solid_count > 0
+    }
+    
+    /// Get the number of blocks that are not air.
+    pub fn solid_count(&self) -> usize {
+        self.solid_count
+    }
+    
+    /// Iterate over the blocks that are not air, with their positions,
+    /// in the same order as iterating over the whole list.
+    pub fn solid_blocks<'a>(&'a self) -> SolidBlocks<'a> {
+        SolidBlocks(self.into_iter())
+    }
+    
+    /// Encode the blocks for storage. The sky light is not stored,
+    /// since it can be recomputed with `light::propagate_sky_light`.
+    ///
+    /// Blocks are run length encoded in index order, each run as
+    /// the block id followed by the run length as a little endian
+    /// `u16`. Most sectors are a few long runs of air or stone.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut blocks = self.blocks.iter().peekable();
+        
+        while let Some(&block) = blocks.next() {
+            let mut run: u16 = 1;
+            while run < u16::max_value() && blocks.peek() == Some(&&block) {
+                blocks.next();
+                run += 1;
+            }
+            
+            out.push(block.id());
+            out.push(run as u8);
+            out.push((run >> 8) as u8);
+        }
+        
+        out
+    }
+    
+    /// Decode blocks stored by `serialize`. The sky light is not
+    /// stored, so every block starts without it, like `new`.
+    pub fn deserialize(data: &[u8]) -> Result<BlockList, DeserializeError> {
+        let mut blocks = [Block::Air; SECTOR_LEN];
+        let mut len = 0;
+        
+        for run in data.chunks(3) {
+            if run.len() < 3 {
+                return Err(DeserializeError::Truncated);
+            }
+            
+            let block = Block::try_from(run[0]).map_err(DeserializeError::UnknownBlock)?;
+            let count = run[1] as usize | ((run[2] as usize) << 8);
+            
+            if len + count > SECTOR_LEN {
+                return Err(DeserializeError::WrongLength(len + count));
+            }
+            
+            for b in &mut blocks[len..len + count] {
+                *b = block;
             }
+            len += count;
         }
         
-        false
+        if len != SECTOR_LEN {
+            return Err(DeserializeError::WrongLength(len));
+        }
+        
+        Ok(BlockList::new(blocks))
     }
     
-    // Determines the internal index of sector coords.
-    fn index(pos: SectorSpaceCoords) -> usize {
+    /// Determine the internal index of sector coords. Iterating
+    /// a `BlockList` visits blocks in index order.
+    pub fn index(pos: SectorSpaceCoords) -> usize {
         let (x, y, z) = (pos.x() as usize, pos.y() as usize, pos.z() as usize);
         
         x + y * SECTOR_SIZE + z * SECTOR_SIZE * SECTOR_SIZE
     }
 }
 
+/// The reason `BlockList::deserialize` failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The data ended partway through a run.
+    Truncated,
+    
+    /// A run had an id that is not a `Block`.
+    UnknownBlock(u8),
+    
+    /// The runs did not add up to exactly one sector of blocks.
+    /// Holds the number of blocks counted before giving up.
+    WrongLength(usize),
+}
+
 /// An iterator over a BlockList.
 pub struct BlockListIter<'a>(iter::Enumerate<slice::Iter<'a, Block>>);
 
@@ -208,33 +527,63 @@ impl<'a> Iterator for BlockListIter<'a> {
     }
 }
 
+// Overwrite a block of a `BlockList`, keeping its count
+// of blocks that are not air up to date.
+fn replace_counted(b: &mut Block, block: Block, solid_count: &mut usize) {
+    match (b.is_air(), block.is_air()) {
+        (true, false) => *solid_count += 1,
+        (false, true) => *solid_count -= 1,
+        _ => {},
+    }
+    
+    *b = block;
+}
+
+/// Iterates over the blocks of a `BlockList` that are not air.
+/// See `BlockList::solid_blocks`.
+pub struct SolidBlocks<'a>(BlockListIter<'a>);
+
+impl<'a> Iterator for SolidBlocks<'a> {
+    type Item = BlockListIterItem<'a>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.find(|i| !i.1.is_air())
+    }
+}
+
 impl<'a> IntoIterator for &'a BlockList {
     type Item = BlockListIterItem<'a>;
     type IntoIter = BlockListIter<'a>;
     
     fn into_iter(self) -> BlockListIter<'a> {
-        BlockListIter(self.0.iter().enumerate())
+        BlockListIter(self.blocks.iter().enumerate())
     }
 }
 
 /// Holds references to all adjacent sectors.
+/// Neighbors that have not been generated are `None`.
+///
+/// Sectors are not padded with copies of their neighbors' border
+/// blocks. Instead, meshing looks one block past the edge of a
+/// sector into the neighbor on that side, so a block at `x = 0`
+/// checks `x = SECTOR_SIZE - 1` of `left`.
 pub struct AdjacentSectors<'a> {
-    pub back: &'a Sector,
-    pub front: &'a Sector,
-    pub top: &'a Sector,
-    pub bottom: &'a Sector,
-    pub left: &'a Sector,
-    pub right: &'a Sector,
+    pub back: Option<&'a Sector>,
+    pub front: Option<&'a Sector>,
+    pub top: Option<&'a Sector>,
+    pub bottom: Option<&'a Sector>,
+    pub left: Option<&'a Sector>,
+    pub right: Option<&'a Sector>,
 }
 
 impl<'a> AdjacentSectors<'a> {
     /// Create a new structure from all 6 neighbors.
-    pub fn new(back: &'a Sector,
-               front: &'a Sector,
-               top: &'a Sector,
-               bottom: &'a Sector,
-               left: &'a Sector,
-               right: &'a Sector) -> AdjacentSectors<'a> {
+    pub fn new(back: Option<&'a Sector>,
+               front: Option<&'a Sector>,
+               top: Option<&'a Sector>,
+               bottom: Option<&'a Sector>,
+               left: Option<&'a Sector>,
+               right: Option<&'a Sector>) -> AdjacentSectors<'a> {
         AdjacentSectors {
             back,
             front,
@@ -244,34 +593,205 @@ impl<'a> AdjacentSectors<'a> {
             right,
         }
     }
+    
+    /// Look up the 6 neighbors of the sector at `pos` in a map of
+    /// loaded sectors, by their sector coordinates.
+    pub fn from_map(sectors: &'a HashMap<(i32, i32, i32), Sector>,
+                    pos: (i32, i32, i32)) -> AdjacentSectors<'a> {
+        let (x, y, z) = pos;
+        
+        Self::new(sectors.get(&(x, y, z - 1)),
+                  sectors.get(&(x, y, z + 1)),
+                  sectors.get(&(x, y + 1, z)),
+                  sectors.get(&(x, y - 1, z)),
+                  sectors.get(&(x - 1, y, z)),
+                  sectors.get(&(x + 1, y, z)))
+    }
+    
+    /// Determine if all 6 neighbors are present.
+    pub fn is_complete(&self) -> bool {
+        self.back.is_some() && self.front.is_some() &&
+        self.top.is_some() && self.bottom.is_some() &&
+        self.left.is_some() && self.right.is_some()
+    }
 }
 
 /// An individual "chunk" of the world.
 pub struct Sector {
     blocks: BlockList,
     model: Option<Model<Vertex>>,
+    atlas_batches: Vec<(usize, Tess<Vertex>)>,
+    wireframe_batches: Vec<(usize, Tess<Vertex>)>,
+    transparent: Option<Tess<Vertex>>,
+    transparent_vertices: Vec<Vertex>,
+    sorted_from: Option<(i32, i32, i32)>,
+    vertex_count: usize,
+    face_masks: Vec<u8>,
+    dirty: Vec<SectorSpaceCoords>,
+    cause: GenerationCause,
+    frontier: bool,
 }
 
 impl Sector {
-    /// Create a sector.
-    pub fn new(blocks: BlockList) -> Sector {
+    /// Create a sector, tagged with the reason it was generated.
+    pub fn new(blocks: BlockList, cause: GenerationCause) -> Sector {
             //let blocks = BlockList([Block::Loam; SECTOR_LEN]);
 
         Sector {
             blocks,
             model: None,
+            atlas_batches: Vec::new(),
+            wireframe_batches: Vec::new(),
+            transparent: None,
+            transparent_vertices: Vec::new(),
+            sorted_from: None,
+            vertex_count: 0,
+            face_masks: Vec::new(),
+            dirty: Vec::new(),
+            cause,
+            frontier: false,
         }
     }
     
+    /// Return why this sector was generated.
+    pub fn cause(&self) -> GenerationCause {
+        self.cause
+    }
+    
     /// Return an immutable reference to this sector's `Model`.
     /// The model may not exist, in which case `None` is returned.
     pub fn model(&self) -> Option<&Model<Vertex>> {
         self.model.as_ref()
     }
     
-    /// Set the `Sector`'s `Model`.
-    pub fn set_model(&mut self, model: Option<Model<Vertex>>) {
-        self.model = model;
+    /// Return a mutable reference to this sector's `Model`, if any.
+    pub fn model_mut(&mut self) -> Option<&mut Model<Vertex>> {
+        self.model.as_mut()
+    }
+    
+    /// Set the `Sector`'s `Model`, along with its number of
+    /// vertices, as returned by `create_model`.
+    pub fn set_model(&mut self, model: Option<(Model<Vertex>, usize)>) {
+        match model {
+            Some((model, vertex_count)) => {
+                self.model = Some(model);
+                self.vertex_count = vertex_count;
+            },
+            None => {
+                self.model = None;
+                self.atlas_batches.clear();
+                self.wireframe_batches.clear();
+                self.transparent = None;
+                self.transparent_vertices = Vec::new();
+                self.vertex_count = 0;
+                self.invalidate_face_masks();
+            },
+        }
+    }
+    
+    /// Return the tesselation of this sector's opaque faces that use
+    /// the terrain atlas `atlas`, if any. Atlas `0` is the `Model`
+    /// itself, and the others are drawn with the `Model`'s transform.
+    pub fn atlas_batch(&self, atlas: usize) -> Option<&Tess<Vertex>> {
+        if atlas == 0 {
+            return self.model.as_ref().map(|m| &m.tess);
+        }
+        
+        self.atlas_batches.iter().find(|b| b.0 == atlas).map(|b| &b.1)
+    }
+    
+    /// Return the edges of the triangles in `atlas_batch(atlas)`, as
+    /// a line tesselation, if the model was built with `wireframe` set.
+    pub fn wireframe_batch(&self, atlas: usize) -> Option<&Tess<Vertex>> {
+        self.wireframe_batches.iter().find(|b| b.0 == atlas).map(|b| &b.1)
+    }
+    
+    /// Return the tesselation of this sector's transparent faces, if
+    /// any. It is drawn with the transform of the sector's `Model`.
+    pub fn transparent(&self) -> Option<&Tess<Vertex>> {
+        self.transparent.as_ref()
+    }
+    
+    /// Rebuild the transparent tesselation if it is missing, or if
+    /// `sort` is set and the camera at `eye` has moved to another
+    /// block since the faces were last sorted. Sorted faces are
+    /// ordered back to front from the camera.
+    ///
+    /// Sorting is per face, by the distance to its center, so it is
+    /// only approximate: faces that cross or interpenetrate may still
+    /// blend in the wrong order.
+    pub fn update_transparent(&mut self, eye: &Translation, sort: bool) {
+        if self.transparent_vertices.is_empty() {
+            self.transparent = None;
+            return;
+        }
+        
+        let local = match self.model {
+            Some(ref model) => [eye.x - model.translation.x,
+                                eye.y - model.translation.y,
+                                eye.z - model.translation.z],
+            None => return,
+        };
+        
+        let key = if sort {
+            Some((local[0].floor() as i32, local[1].floor() as i32, local[2].floor() as i32))
+        } else {
+            None
+        };
+        
+        if self.transparent.is_some() && self.sorted_from == key {
+            return;
+        }
+        
+        if sort {
+            self.transparent_vertices = mesh_gen::sort_quads(&self.transparent_vertices, local);
+        }
+        
+        self.transparent = Some(Tess::new(Mode::Triangle,
+                                          TessVertices::Fill(&self.transparent_vertices),
+                                          None));
+        self.sorted_from = key;
+    }
+    
+    /// Discard the cached face visibility of every block, so that it
+    /// is all recomputed by the next `create_model`. This is needed
+    /// when a neighboring sector is loaded, or how frontier faces
+    /// are drawn changes.
+    pub fn invalidate_face_masks(&mut self) {
+        self.face_masks = Vec::new();
+        self.dirty.clear();
+    }
+    
+    /// Mark the cached face visibility of one block as stale, because
+    /// it or a block next to it changed. Only dirty blocks are
+    /// recomputed by the next `create_model`.
+    pub fn mark_dirty(&mut self, pos: SectorSpaceCoords) {
+        if !self.face_masks.is_empty() {
+            self.dirty.push(pos);
+        }
+    }
+    
+    /// Return the number of vertices in the model,
+    /// or `0` if there is none.
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+    
+    /// Return the number of triangles in the model,
+    /// or `0` if there is none.
+    pub fn triangle_count(&self) -> usize {
+        self.vertex_count / 3
+    }
+    
+    /// Determine if the model was built while some neighbors
+    /// were missing, and should be rebuilt once they arrive.
+    pub fn is_frontier(&self) -> bool {
+        self.frontier
+    }
+    
+    /// Mark whether the model was built with missing neighbors.
+    pub fn set_frontier(&mut self, frontier: bool) {
+        self.frontier = frontier;
     }
     
     /// Return this sector's `BlockList`.
@@ -279,14 +799,69 @@ impl Sector {
         &self.blocks
     }
     
-    /// Create the `Model` for the `Sector`.
-    pub fn create_model(&self, resources: &Resources, pos: (i32, i32, i32),
-                  adjacent: &AdjacentSectors) -> Option<Model<Vertex>> {
+    /// Return this sector's `BlockList` for editing.
+    /// The model is not rebuilt automatically.
+    pub fn blocks_mut(&mut self) -> &mut BlockList {
+        &mut self.blocks
+    }
+    
+    /// Create the `Model` for the `Sector`, and count its vertices.
+    /// `pos` is the sector position relative to the render origin.
+    /// If `draw_frontier` is set, faces bordering missing neighbors are drawn.
+    /// If `edge_blending` is set, see `mesh_gen::generate_block_vertices`.
+    /// If `greedy` is set, faces are merged with
+    /// `mesh_gen::generate_block_vertices_greedy`.
+    /// If `wireframe` is set, the edges of the opaque triangles are also
+    /// kept as line tesselations, see `wireframe_batch`.
+    /// Blocks in `lights` emit that colored light.
+    ///
+    /// The model holds the faces from the first terrain atlas. Faces
+    /// from other atlases are kept as separate batches, see `atlas_batch`.
+    /// Transparent faces are kept aside, and are not part of the model
+    /// until the next `update_transparent`. They are all still counted.
+    ///
+    /// The visible faces of each block are cached, and only dirty
+    /// blocks are rechecked. The cache is about as large as the blocks.
+    pub fn create_model(&mut self, resources: &Resources, pos: (i32, i32, i32),
+                  adjacent: &AdjacentSectors, draw_frontier: bool,
+                  edge_blending: bool, greedy: bool, wireframe: bool,
+                  lights: &HashMap<Block, LightColor>) -> Option<(Model<Vertex>, usize)> {
         if self.blocks.needs_rendering() {
-            let terrain_tex = resources.terrain_tex();
+            mesh_gen::update_face_masks(&mut self.face_masks, &self.dirty, &self.blocks,
+                                        adjacent, draw_frontier);
+            self.dirty.clear();
             
-            let vertices = mesh_gen::generate_block_vertices(&self.blocks, adjacent, &terrain_tex.1);
-            let tess = Tess::new(Mode::Triangle, TessVertices::Fill(&vertices), None);
+            let light = LightMap::propagate(&self.blocks, lights);
+            
+            let generate = if greedy {
+                mesh_gen::generate_block_vertices_greedy
+            } else {
+                mesh_gen::generate_block_vertices
+            };
+            let (batches, transparent) = generate(&self.blocks, &self.face_masks, adjacent,
+                                                  edge_blending, light.as_ref(),
+                                                  resources.terrain_layouts());
+            let tess = Tess::new(Mode::Triangle, TessVertices::Fill(&batches[0]), None);
+            
+            let mut count = transparent.len();
+            self.atlas_batches.clear();
+            self.wireframe_batches.clear();
+            for (atlas, vertices) in batches.iter().enumerate() {
+                count += vertices.len();
+                
+                if atlas != 0 && !vertices.is_empty() {
+                    self.atlas_batches.push((atlas, Tess::new(Mode::Triangle,
+                                                              TessVertices::Fill(vertices),
+                                                              None)));
+                }
+                
+                if wireframe && !vertices.is_empty() {
+                    let edges = mesh_gen::triangle_edges(vertices.len());
+                    self.wireframe_batches.push((atlas, Tess::new(Mode::Line,
+                                                                  TessVertices::Fill(vertices),
+                                                                  Some(&edges))));
+                }
+            }
             
             let translation = Translation::new((pos.0 * SECTOR_SIZE as i32) as f32,
                                                (pos.1 * SECTOR_SIZE as i32) as f32,
@@ -294,9 +869,262 @@ impl Sector {
                                            
             //println!("translation: {:?}", translation);
             
-            Some(Model::with_translation(tess, terrain_tex, translation))
+            self.transparent = None;
+            self.transparent_vertices = transparent;
+            
+            Some((Model::with_translation(tess, resources.terrain_tex(), translation), count))
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::world_gen::WorldGen;
+    use std::ptr;
+    
+    #[test]
+    fn serialize_round_trip() {
+        let blocks = WorldGen::new().generate((0, 0, 0));
+        let loaded = BlockList::deserialize(&blocks.serialize()).unwrap();
+        
+        for ((coords, a), (_, b)) in blocks.into_iter().zip(&loaded) {
+            assert_eq!(a, b, "at {:?}", coords);
+        }
+    }
+    
+    #[test]
+    fn deserialize_truncated_input_errors() {
+        let data = BlockList::new_air().serialize();
+        
+        assert_eq!(BlockList::deserialize(&data[..data.len() - 1]).err(),
+                   Some(DeserializeError::Truncated));
+        assert_eq!(BlockList::deserialize(&[]).err(),
+                   Some(DeserializeError::WrongLength(0)));
+        assert_eq!(BlockList::deserialize(&[0, 16, 0]).err(),
+                   Some(DeserializeError::WrongLength(16)));
+    }
+    
+    #[test]
+    fn id_round_trip() {
+        for &block in Block::all() {
+            assert_eq!(Block::from_id(block.id()), Some(block));
+            assert_eq!(Block::try_from(block.id()), Ok(block));
+        }
+    }
+    
+    #[test]
+    fn unknown_id_is_none() {
+        let unknown = ALL_BLOCKS.len() as u8;
+        
+        assert_eq!(Block::from_id(unknown), None);
+        assert_eq!(Block::from_id(255), None);
+        assert_eq!(Block::try_from(unknown), Err(unknown));
+    }
+    
+    #[test]
+    fn adjacent_sectors_from_map() {
+        let center = (3, -1, 7);
+        let sides = [(3, -1, 6), (3, -1, 8), (3, 0, 7), (3, -2, 7), (2, -1, 7), (4, -1, 7)];
+        
+        let mut sectors = HashMap::new();
+        for &pos in sides.iter().chain(&[center]) {
+            sectors.insert(pos, Sector::new(BlockList::new_air(), GenerationCause::Streaming));
+        }
+        
+        let adjacent = AdjacentSectors::from_map(&sectors, center);
+        let found = [adjacent.back, adjacent.front, adjacent.top,
+                     adjacent.bottom, adjacent.left, adjacent.right];
+        
+        for (side, pos) in found.iter().zip(&sides) {
+            assert!(ptr::eq(side.unwrap(), &sectors[pos]), "side at {:?}", pos);
+        }
+        assert!(adjacent.is_complete());
+        
+        sectors.remove(&(3, 0, 7));
+        let adjacent = AdjacentSectors::from_map(&sectors, center);
+        assert!(adjacent.top.is_none());
+        assert!(!adjacent.is_complete());
+    }
+    
+    #[test]
+    fn last_coords_in_sector_are_valid() {
+        let last = (SECTOR_SIZE - 1) as u8;
+        let corner = SectorSpaceCoords::new(last, last, last);
+        
+        assert_eq!(BlockList::index(corner), SECTOR_LEN - 1);
+        assert!(BlockList::index(corner) < SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE);
+        assert!(corner.top().is_none() && corner.front().is_none() && corner.right().is_none());
+    }
+    
+    #[test]
+    fn index_visits_every_block_once() {
+        let indices: Vec<_> = BlockList::new_air().into_iter()
+                                                  .map(|(c, _)| BlockList::index(c))
+                                                  .collect();
+        
+        assert_eq!(indices, (0..SECTOR_LEN).collect::<Vec<_>>());
+    }
+    
+    #[test]
+    fn try_new_checks_range() {
+        assert!(SectorSpaceCoords::try_new(0, 12, 30).is_some());
+        assert!(SectorSpaceCoords::try_new(31, 31, 31).is_some());
+        assert!(SectorSpaceCoords::try_new(32, 0, 0).is_none());
+        assert!(SectorSpaceCoords::try_new(0, 32, 0).is_none());
+        assert!(SectorSpaceCoords::try_new(0, 0, 32).is_none());
+        assert!(SectorSpaceCoords::try_new(255, 255, 255).is_none());
+    }
+    
+    #[test]
+    #[should_panic]
+    fn new_panics_out_of_range() {
+        SectorSpaceCoords::new(0, 32, 0);
+    }
+    
+    // The positions of every block equal to `block`.
+    fn positions_of(blocks: &BlockList, block: Block) -> Vec<(u8, u8, u8)> {
+        blocks.into_iter()
+              .filter(|&(_, b)| *b == block)
+              .map(|(c, _)| (c.x(), c.y(), c.z()))
+              .collect()
+    }
+    
+    #[test]
+    fn fill_box_whole_sector() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((0, 0, 0), (31, 31, 31), Block::Loam);
+        
+        assert_eq!(positions_of(&blocks, Block::Loam).len(), SECTOR_LEN);
+    }
+    
+    #[test]
+    fn fill_box_sub_box() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((2, 3, 4), (3, 4, 4), Block::Loam);
+        
+        assert_eq!(positions_of(&blocks, Block::Loam),
+                   vec![(2, 3, 4), (3, 3, 4), (2, 4, 4), (3, 4, 4)]);
+    }
+    
+    #[test]
+    fn fill_box_swapped_corners() {
+        let (mut a, mut b) = (BlockList::new_air(), BlockList::new_air());
+        a.fill_box((2, 3, 4), (6, 8, 5), Block::Loam);
+        b.fill_box((6, 8, 5), (2, 3, 4), Block::Loam);
+        
+        assert_eq!(positions_of(&a, Block::Loam), positions_of(&b, Block::Loam));
+        assert_eq!(positions_of(&a, Block::Loam).len(), 5 * 6 * 2);
+    }
+    
+    #[test]
+    fn fill_box_partly_outside() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((-2, 30, 5), (0, 40, 5), Block::Loam);
+        
+        assert_eq!(positions_of(&blocks, Block::Loam), vec![(0, 30, 5), (0, 31, 5)]);
+        
+        blocks.fill_box((-5, -5, -5), (-1, 10, 10), Block::Loam);
+        assert_eq!(positions_of(&blocks, Block::Loam).len(), 2);
+    }
+    
+    fn solid_positions(blocks: &BlockList) -> Vec<(u8, u8, u8)> {
+        blocks.solid_blocks().map(|(c, _)| (c.x(), c.y(), c.z())).collect()
+    }
+    
+    #[test]
+    fn solid_blocks_of_air_is_empty() {
+        assert!(BlockList::new_air().solid_blocks().next().is_none());
+    }
+    
+    #[test]
+    fn solid_blocks_skips_air() {
+        let mut blocks = BlockList::new_air();
+        blocks.set(SectorSpaceCoords::new(31, 0, 0), Block::Sand);
+        blocks.set(SectorSpaceCoords::new(0, 5, 0), Block::Loam);
+        blocks.set(SectorSpaceCoords::new(2, 0, 31), Block::Limestone);
+        
+        let found: Vec<_> = blocks.solid_blocks().map(|(c, b)| ((c.x(), c.y(), c.z()), *b)).collect();
+        assert_eq!(found, vec![((31, 0, 0), Block::Sand),
+                               ((0, 5, 0), Block::Loam),
+                               ((2, 0, 31), Block::Limestone)]);
+    }
+    
+    #[test]
+    fn solid_blocks_match_full_iteration() {
+        let blocks = WorldGen::new().generate((0, 0, 0));
+        let expected: Vec<_> = blocks.into_iter()
+                                     .filter(|&(_, b)| !b.is_air())
+                                     .map(|(c, _)| (c.x(), c.y(), c.z()))
+                                     .collect();
+        
+        assert!(!expected.is_empty() && expected.len() < SECTOR_LEN);
+        assert_eq!(solid_positions(&blocks), expected);
+        assert_eq!(expected.len(), blocks.solid_count());
+    }
+    
+    #[test]
+    fn solid_count_follows_set() {
+        let mut blocks = BlockList::new_air();
+        let pos = SectorSpaceCoords::new(4, 5, 6);
+        assert_eq!(blocks.solid_count(), 0);
+        assert!(!blocks.needs_rendering());
+        
+        blocks.set(pos, Block::Loam);
+        assert_eq!(blocks.solid_count(), 1);
+        assert!(blocks.needs_rendering());
+        
+        // Replacing one solid block with another keeps the count.
+        blocks.set(pos, Block::Sand);
+        assert_eq!(blocks.solid_count(), 1);
+        
+        // Clearing a block twice only counts it once.
+        blocks.set(pos, Block::Air);
+        blocks.set(pos, Block::Air);
+        assert_eq!(blocks.solid_count(), 0);
+        assert!(!blocks.needs_rendering());
+    }
+    
+    #[test]
+    fn solid_count_follows_fill() {
+        let mut blocks = BlockList::new_air();
+        
+        blocks.fill(Block::Limestone);
+        assert_eq!(blocks.solid_count(), SECTOR_LEN);
+        
+        blocks.fill(Block::Air);
+        assert_eq!(blocks.solid_count(), 0);
+    }
+    
+    #[test]
+    fn solid_count_follows_fill_box() {
+        let mut blocks = BlockList::new_air();
+        
+        blocks.fill_box((0, 0, 0), (3, 3, 3), Block::Loam);
+        assert_eq!(blocks.solid_count(), 64);
+        
+        // Overlapping the first box only counts the new blocks.
+        blocks.fill_box((2, 2, 2), (5, 5, 5), Block::Sand);
+        assert_eq!(blocks.solid_count(), 64 + 64 - 8);
+        
+        blocks.fill_box((0, 0, 0), (1, 1, 1), Block::Air);
+        assert_eq!(blocks.solid_count(), 64 + 64 - 8 - 8);
+        
+        // Only the part inside the sector is counted.
+        blocks.fill_box((30, 0, 0), (40, 0, 0), Block::Loam);
+        assert_eq!(blocks.solid_count(), 64 + 64 - 8 - 8 + 2);
+    }
+    
+    #[test]
+    fn solid_count_survives_serialize() {
+        let mut blocks = BlockList::new_air();
+        blocks.fill_box((1, 2, 3), (10, 4, 20), Block::Limestone);
+        blocks.set(SectorSpaceCoords::new(31, 31, 31), Block::Leaves);
+        
+        let loaded = BlockList::deserialize(&blocks.serialize()).unwrap();
+        assert_eq!(loaded.solid_count(), 10 * 3 * 18 + 1);
+        assert_eq!(loaded.solid_count(), blocks.solid_count());
+    }
+}
@@ -1,12 +1,119 @@
 //! Procedural world generation.
 
-use noise::{BasicMulti, MultiFractal, NoiseModule};
-use super::SECTOR_SIZE;
+use std::collections::HashMap;
+use noise::{BasicMulti, MultiFractal, NoiseModule, Seedable};
+use super::{light, SECTOR_SIZE};
 use super::voxel::{Block, BlockList, SectorSpaceCoords};
+use maths;
 
 const SECTOR_SIZE_F: f32 = SECTOR_SIZE as f32;
 
+/// The default sea level, as a height within the surface sectors.
+pub const DEFAULT_SEA_LEVEL: usize = 12;
+
+/// The default noise value above which caves are carved.
+pub const DEFAULT_CAVE_THRESHOLD: f32 = 0.45;
+
+/// The default frequency of the cave noise, per block.
+pub const DEFAULT_CAVE_FREQUENCY: f32 = 0.04;
+
+/// The ores scattered by a new `WorldGen`.
+pub const DEFAULT_ORES: [OreConfig; 2] = [
+    OreConfig { block: Block::CoalOre, frequency: 0.15, threshold: 0.55, max_y: -1 },
+    OreConfig { block: Block::IronOre, frequency: 0.2, threshold: 0.65, max_y: -12 },
+];
+
+// How far apart, in noise space, each ore samples the ore noise,
+// so that different ores do not form the same veins.
+const ORE_OFFSET: f32 = 1000.;
+
+// The frequency of the biome noise, per block.
+const BIOME_FREQUENCY: f32 = 0.002;
+
+// The biome noise values that separate deserts from
+// plains, and plains from mountains.
+const DESERT_BELOW: f32 = -0.3;
+const MOUNTAINS_ABOVE: f32 = 0.3;
+
+// How far, in biome noise, the height amplitude blends
+// between neighboring biomes on each side of a border.
+const BIOME_BLEND: f32 = 0.1;
+
+/// The kind of landscape of a surface column. See `WorldGen::biome_at`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+}
+
+impl Biome {
+    /// Get the block that the surface is made of.
+    pub fn surface_block(&self) -> Block {
+        match *self {
+            Biome::Desert => Block::Sand,
+            Biome::Plains | Biome::Mountains => Block::Grass,
+        }
+    }
+    
+    /// Get how far, in blocks, the terrain rises and
+    /// falls around the middle of the surface sectors.
+    pub fn height_amplitude(&self) -> f32 {
+        match *self {
+            Biome::Plains => 40.,
+            Biome::Desert => 15.,
+            Biome::Mountains => 60.,
+        }
+    }
+    
+    /// Get the tree noise value above which trees may grow,
+    /// or `None` if the biome has no trees. Higher is sparser.
+    pub fn tree_threshold(&self) -> Option<f32> {
+        match *self {
+            Biome::Plains => Some(0.2),
+            Biome::Desert => None,
+            Biome::Mountains => Some(0.4),
+        }
+    }
+}
+
+/// A kind of ore scattered through the limestone. See `WorldGen::with_ore`.
+#[derive(Clone, Copy, Debug)]
+pub struct OreConfig {
+    /// The block placed.
+    pub block: Block,
+    
+    /// The frequency of the ore noise, per block. Higher
+    /// frequencies make smaller, more frequent veins.
+    pub frequency: f32,
+    
+    /// The noise value above which ore is placed. Higher
+    /// thresholds make the ore rarer.
+    pub threshold: f32,
+    
+    /// The highest world block `y` the ore is found at.
+    pub max_y: i32,
+}
+
 /// The world generator.
+///
+/// Below the limestone layer, every sector is filled with the
+/// `void_block` (`Block::Bedrock` by default), so there is always
+/// ground and the sky is never visible through the world's floor.
+///
+/// Surface columns lower than the sea level are topped up with
+/// water to that height. Trees only grow above the water.
+///
+/// Caves are carved out of the surface and limestone sectors where
+/// 3D noise, sampled at world coordinates, is above a threshold.
+/// Since the noise is continuous, caves run across sector borders.
+/// Columns under the sea are not carved, so water never floats.
+///
+/// The surface is split into biomes by a low frequency 2D noise,
+/// sampled at world coordinates like the caves. See `Biome`.
+///
+/// Ores then replace limestone, never any other block, where their
+/// own 3D noise is above a threshold. See `OreConfig`.
 #[derive(Clone)]
 pub struct WorldGen {
     //perlin: Perlin,
@@ -14,10 +121,27 @@ pub struct WorldGen {
     compression: BasicMulti<f32>,
     general_height: BasicMulti<f32>,
     tree: (BasicMulti<f32>, BasicMulti<f32>),
+    caves: BasicMulti<f32>,
+    biomes: BasicMulti<f32>,
+    cave_threshold: f32,
+    cave_frequency: f32,
+    ore_noise: BasicMulti<f32>,
+    ores: Vec<OreConfig>,
+    void_block: Block,
+    sea_level: usize,
+    shape: Shape,
+}
+
+// The overall form of the generated world.
+#[derive(Clone, Copy, Debug)]
+enum Shape {
+    Terrain,
+    SphereShell { radius: f32, thickness: f32 },
 }
 
 impl WorldGen {
-    /// Create a new `WorldGen`.
+    /// Create a new `WorldGen`. The noise uses fixed seeds,
+    /// so every world made this way is the same.
     pub fn new() -> WorldGen {
         WorldGen {
             //perlin: Perlin::new(),
@@ -26,16 +150,159 @@ impl WorldGen {
             general_height: BasicMulti::new().set_octaves(4).set_frequency(0.5),
             tree: (BasicMulti::new().set_frequency(0.01),
                    BasicMulti::new().set_frequency(1.0)),
+            caves: BasicMulti::new().set_octaves(2),
+            biomes: BasicMulti::new().set_octaves(2),
+            cave_threshold: DEFAULT_CAVE_THRESHOLD,
+            cave_frequency: DEFAULT_CAVE_FREQUENCY,
+            ore_noise: BasicMulti::new().set_octaves(2),
+            ores: DEFAULT_ORES.to_vec(),
+            void_block: Block::Bedrock,
+            sea_level: DEFAULT_SEA_LEVEL,
+            shape: Shape::Terrain,
+        }
+    }
+    
+    /// Create a new `WorldGen` whose noise is derived from `seed`.
+    /// Generators with the same seed produce identical sectors.
+    pub fn with_seed(seed: u32) -> WorldGen {
+        let gen = WorldGen::new();
+        let seed_at = |i: u32| seed.wrapping_add(i) as usize;
+        
+        WorldGen {
+            base_terrain: gen.base_terrain.set_seed(seed_at(0)),
+            compression: gen.compression.set_seed(seed_at(1)),
+            general_height: gen.general_height.set_seed(seed_at(2)),
+            tree: ((gen.tree.0).set_seed(seed_at(3)),
+                   (gen.tree.1).set_seed(seed_at(4))),
+            caves: gen.caves.set_seed(seed_at(5)),
+            ore_noise: gen.ore_noise.set_seed(seed_at(6)),
+            biomes: gen.biomes.set_seed(seed_at(7)),
+            ..gen
+        }
+    }
+    
+    /// Create a `WorldGen` for testing, which generates only a hollow
+    /// sphere of limestone centered on the world origin. Blocks whose
+    /// distance from the origin is within `thickness` below `radius`
+    /// are solid, and the rest are air.
+    ///
+    /// The curved surface faces every direction, which makes culling
+    /// and winding bugs easy to spot.
+    pub fn sphere_shell(radius: f32, thickness: f32) -> WorldGen {
+        WorldGen {
+            shape: Shape::SphereShell { radius, thickness },
+            ..WorldGen::new()
+        }
+    }
+    
+    /// Use `block` to fill the sectors below the limestone layer.
+    /// `Block::Air` leaves the bottom of the world open.
+    pub fn with_void_block(mut self, block: Block) -> WorldGen {
+        self.void_block = block;
+        self
+    }
+    
+    /// Fill the surface sectors with water up to `level`, a height
+    /// within the sector from `0` to `SECTOR_SIZE`. `0` means no water.
+    pub fn with_sea_level(mut self, level: usize) -> WorldGen {
+        self.sea_level = level.min(SECTOR_SIZE);
+        self
+    }
+    
+    /// Carve caves where the cave noise is above `threshold`, sampled
+    /// at world block coordinates times `frequency`. Lower thresholds
+    /// carve more, and a threshold above `1` carves nothing. Higher
+    /// frequencies make smaller, more frequent caves.
+    pub fn with_caves(mut self, threshold: f32, frequency: f32) -> WorldGen {
+        self.cave_threshold = threshold;
+        self.cave_frequency = frequency;
+        self
+    }
+    
+    /// Also scatter the ore described by `ore`. Ores are placed
+    /// in the order they were added, so where the veins of two
+    /// ores overlap, the first one wins.
+    pub fn with_ore(mut self, ore: OreConfig) -> WorldGen {
+        self.ores.push(ore);
+        self
+    }
+    
+    /// Scatter exactly these ores, replacing the `DEFAULT_ORES`.
+    /// An empty list places no ore.
+    pub fn with_ores(mut self, ores: Vec<OreConfig>) -> WorldGen {
+        self.ores = ores;
+        self
+    }
+    
+    /// Get the ores scattered by this generator.
+    pub fn ores(&self) -> &[OreConfig] {
+        &self.ores
+    }
+    
+    /// Get the biome of the surface column at world block
+    /// coordinates `(world_x, world_z)`.
+    pub fn biome_at(&self, world_x: i32, world_z: i32) -> Biome {
+        let value = self.biome_value(world_x, world_z);
+        
+        if value < DESERT_BELOW {
+            Biome::Desert
+        } else if value > MOUNTAINS_ABOVE {
+            Biome::Mountains
+        } else {
+            Biome::Plains
         }
     }
     
+    /// Get how far, in blocks, the terrain of the surface column at
+    /// world block coordinates `(world_x, world_z)` rises and falls.
+    ///
+    /// This is the `Biome::height_amplitude` of `biome_at`, except
+    /// near a border between biomes, where it blends smoothly into
+    /// the neighboring biome's, so that there are no cliffs along
+    /// the border.
+    pub fn height_amplitude_at(&self, world_x: i32, world_z: i32) -> f32 {
+        let value = self.biome_value(world_x, world_z);
+        
+        // The fraction of the way across the blend around `border`.
+        let across = |border: f32| {
+            ((value - border + BIOME_BLEND) / (2. * BIOME_BLEND)).max(0.).min(1.)
+        };
+        
+        let plains = Biome::Plains.height_amplitude();
+        if value < 0. {
+            maths::lerp(Biome::Desert.height_amplitude(), plains, across(DESERT_BELOW))
+        } else {
+            maths::lerp(plains, Biome::Mountains.height_amplitude(), across(MOUNTAINS_ABOVE))
+        }
+    }
+    
+    // The biome noise at a surface column. See `biome_at`.
+    fn biome_value(&self, world_x: i32, world_z: i32) -> f32 {
+        self.biomes.get([world_x as f32 * BIOME_FREQUENCY,
+                         world_z as f32 * BIOME_FREQUENCY])
+    }
+    
     /*
     pub fn generate(&self, sector: (i32, i32, i32)) -> BlockList {
         
     }
     */
     
+    /// Generate the blocks of a sector, lit by the sky if it
+    /// is open to it. See `light::propagate_sky_light`.
     pub fn generate(&self, sector: (i32, i32, i32)) -> BlockList {
+        let mut list = self.generate_blocks(sector);
+        self.scatter_ores(&mut list, sector);
+        light::propagate_sky_light(&mut list, light::open_to_sky(sector));
+        
+        list
+    }
+    
+    fn generate_blocks(&self, sector: (i32, i32, i32)) -> BlockList {
+        if let Shape::SphereShell { radius, thickness } = self.shape {
+            return Self::generate_sphere_shell(sector, radius, thickness);
+        }
+        
         /*
         if sector.1 > 0 {
             BlockList::new(
@@ -119,24 +386,51 @@ impl WorldGen {
                         [(fx + SECTOR_SIZE_F * s0) * 0.007 * comp,
                          (fz + SECTOR_SIZE_F * s2) * 0.007 * comp]) * general_h;
                     
+                    let (wx, wz) = (x as i32 + SECTOR_SIZE as i32 * sector.0,
+                                    z as i32 + SECTOR_SIZE as i32 * sector.2);
+                    let biome = self.biome_at(wx, wz);
+                    
                     let middle = SECTOR_SIZE_F / 2.;
                     
-                    let highest = (middle + height * 40.).max(0.).min(SECTOR_SIZE_F) as usize;
+                    // Clamped to the sector, so every `y` below
+                    // is a valid `SectorSpaceCoords` component.
+                    let highest = (middle + height * self.height_amplitude_at(wx, wz))
+                                  .max(0.).min(SECTOR_SIZE_F) as usize;
                     
                     //println!("highest: {}", highest);
                     
                     for y in 0..highest {
                         list.set(SectorSpaceCoords::new(x as u8, y as u8, z as u8),
-                                 Block::Grass);
+                                 biome.surface_block());
                     }
                     
-                    // Trees
-                    if x >= 3 && x <= SECTOR_SIZE - 3 && z >= 3 && z <= SECTOR_SIZE - 3 && highest < SECTOR_SIZE - 8 {
+                    for y in highest..self.sea_level {
+                        list.set(SectorSpaceCoords::new(x as u8, y as u8, z as u8),
+                                 Block::Water);
+                    }
+                    
+                    // Caves under the sea would leave the water
+                    // floating over air, so those columns are skipped.
+                    if highest >= self.sea_level {
+                        self.carve_column(&mut list, sector, (x, z), highest);
+                    }
+                    
+                    // Trees, which need solid ground under the trunk.
+                    let grounded = highest > 0 &&
+                        !list.get(SectorSpaceCoords::new(x as u8, highest as u8 - 1, z as u8)).is_air();
+                    
+                    let tree_threshold = match biome.tree_threshold() {
+                        Some(threshold) => threshold,
+                        None => continue,
+                    };
+                    
+                    if grounded && highest >= self.sea_level &&
+                       x >= 3 && x <= SECTOR_SIZE - 3 && z >= 3 && z <= SECTOR_SIZE - 3 && highest < SECTOR_SIZE - 8 {
                         let tree_chance = self.tree.0.get(
                             [fx + SECTOR_SIZE_F * s0 * 1.1,
                              fz + SECTOR_SIZE_F * s2 * 1.1]);
                         
-                        if tree_chance > 0.2 {
+                        if tree_chance > tree_threshold {
                             let tree_chance2 = self.tree.1.get(
                                 [fx / 2. + SECTOR_SIZE_F * s0 * 1.1,
                                  fz / 2. + SECTOR_SIZE_F * s2 * 1.1]);
@@ -150,15 +444,13 @@ impl WorldGen {
                                 for h in 0..8 {
                                     list.set(SectorSpaceCoords::new(x as u8, (h + highest) as u8, z as u8),
                                              Block::Tree);
-                                    for dx in -2i32..3 {
-                                        for dy in 4i32..8 {
-                                            for dz in -2i32..3 {
-                                                list.set(SectorSpaceCoords::new((x as i32 + dx) as u8, (highest as i32 + dy) as u8, (z as i32  + dz) as u8),
-                                                         Block::Leaves);
-                                            }
-                                        }
-                                    }
                                 }
+                                
+                                // The leaves hide the top of the trunk. Any
+                                // past the edge of the sector are skipped.
+                                let (x, y, z) = (x as i32, highest as i32, z as i32);
+                                list.fill_box((x - 2, y + 4, z - 2), (x + 2, y + 7, z + 2),
+                                              Block::Leaves);
                             }
                         }
                     }
@@ -167,9 +459,342 @@ impl WorldGen {
             
             list
         } else if sector.1 == -1 {
-            BlockList::new([Block::Limestone; SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE])
+            let mut list = BlockList::new([Block::Limestone; SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE]);
+            
+            for x in 0..SECTOR_SIZE {
+                for z in 0..SECTOR_SIZE {
+                    self.carve_column(&mut list, sector, (x, z), SECTOR_SIZE);
+                }
+            }
+            
+            list
+        } else if sector.1 < -1 {
+            BlockList::new([self.void_block; SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE])
         } else {
             BlockList::new_air()
         }
     }
+    
+    // Carve caves out of the column at `(x, z)`, below `top`.
+    fn carve_column(&self, list: &mut BlockList, sector: (i32, i32, i32),
+                    column: (usize, usize), top: usize) {
+        let (x, z) = column;
+        let f = self.cave_frequency;
+        let wx = (x as f32 + SECTOR_SIZE_F * sector.0 as f32) * f;
+        let wz = (z as f32 + SECTOR_SIZE_F * sector.2 as f32) * f;
+        
+        for y in 0..top {
+            let wy = (y as f32 + SECTOR_SIZE_F * sector.1 as f32) * f;
+            
+            if self.caves.get([wx, wy, wz]) > self.cave_threshold {
+                list.set(SectorSpaceCoords::new(x as u8, y as u8, z as u8), Block::Air);
+            }
+        }
+    }
+    
+    // Replace limestone with ore where each ore's noise is
+    // above its threshold. The test shapes have no ore.
+    fn scatter_ores(&self, list: &mut BlockList, sector: (i32, i32, i32)) {
+        if let Shape::SphereShell { .. } = self.shape {
+            return;
+        }
+        
+        let base_y = SECTOR_SIZE as i32 * sector.1;
+        
+        for (i, ore) in self.ores.iter().enumerate() {
+            if base_y > ore.max_y {
+                continue;
+            }
+            
+            let f = ore.frequency;
+            let offset = ORE_OFFSET * i as f32;
+            let top = ((ore.max_y - base_y + 1) as usize).min(SECTOR_SIZE);
+            
+            for x in 0..SECTOR_SIZE {
+                for z in 0..SECTOR_SIZE {
+                    let wx = (x as f32 + SECTOR_SIZE_F * sector.0 as f32) * f + offset;
+                    let wz = (z as f32 + SECTOR_SIZE_F * sector.2 as f32) * f;
+                    
+                    for y in 0..top {
+                        let coords = SectorSpaceCoords::new(x as u8, y as u8, z as u8);
+                        if *list.get(coords) != Block::Limestone {
+                            continue;
+                        }
+                        
+                        let wy = (y as i32 + base_y) as f32 * f;
+                        if self.ore_noise.get([wx, wy, wz]) > ore.threshold {
+                            list.set(coords, ore.block);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    fn generate_sphere_shell(sector: (i32, i32, i32), radius: f32, thickness: f32) -> BlockList {
+        let mut list = BlockList::new_air();
+        
+        for x in 0..SECTOR_SIZE {
+            for y in 0..SECTOR_SIZE {
+                for z in 0..SECTOR_SIZE {
+                    let wx = x as f32 + SECTOR_SIZE_F * sector.0 as f32;
+                    let wy = y as f32 + SECTOR_SIZE_F * sector.1 as f32;
+                    let wz = z as f32 + SECTOR_SIZE_F * sector.2 as f32;
+                    
+                    let dist = (wx * wx + wy * wy + wz * wz).sqrt();
+                    
+                    if dist <= radius && dist > radius - thickness {
+                        list.set(SectorSpaceCoords::new(x as u8, y as u8, z as u8),
+                                 Block::Limestone);
+                    }
+                }
+            }
+        }
+        
+        list
+    }
+}
+
+/// Generate every sector within `radius` sectors of `center` along
+/// each axis, a cube of `(2 * radius + 1)^3` sectors, keyed by their
+/// sector coordinates. A negative radius generates nothing.
+///
+/// This needs no rendering resources or OpenGL context, so it
+/// can be used to check or benchmark world generation on its own.
+pub fn generate_region(gen: &WorldGen, center: (i32, i32, i32), radius: i32)
+        -> HashMap<(i32, i32, i32), BlockList> {
+    let mut region = HashMap::new();
+    
+    for x in -radius..radius + 1 {
+        for y in -radius..radius + 1 {
+            for z in -radius..radius + 1 {
+                let sector = (center.0 + x, center.1 + y, center.2 + z);
+                region.insert(sector, gen.generate(sector));
+            }
+        }
+    }
+    
+    region
+}
+
+/// Hash a seed and block coordinates into a well mixed `u32`.
+/// The same inputs always produce the same value, so decorations
+/// can make per-block choices without any generator state.
+pub fn hash_coords(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = mix(seed ^ 0x9E37_79B9);
+    h = mix(h ^ x as u32);
+    h = mix(h ^ y as u32);
+    mix(h ^ z as u32)
+}
+
+/// Get a deterministic value in `[0, 1)` for a seed and block coordinates.
+pub fn rng_at(seed: u32, x: i32, y: i32, z: i32) -> f32 {
+    (hash_coords(seed, x, y, z) >> 8) as f32 / (1 << 24) as f32
+}
+
+// The MurmurHash3 finalizer.
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^= h >> 16;
+    
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn surface_sector_has_air_and_solid_blocks() {
+        let blocks = WorldGen::new().generate((0, 0, 0));
+        
+        assert!(blocks.solid_count() > 0);
+        assert!(blocks.solid_count() < SECTOR_SIZE * SECTOR_SIZE * SECTOR_SIZE);
+    }
+    
+    #[test]
+    fn same_seed_generates_same_sectors() {
+        let (a, b) = (WorldGen::with_seed(42), WorldGen::with_seed(42));
+        
+        for &sector in &[(0, 0, 0), (3, 0, -2), (1, -1, 1)] {
+            assert_eq!(a.generate(sector).serialize(), b.generate(sector).serialize());
+        }
+    }
+    
+    #[test]
+    fn different_seeds_generate_different_sectors() {
+        let (a, b) = (WorldGen::with_seed(1), WorldGen::with_seed(2));
+        
+        assert!(a.generate((0, 0, 0)).serialize() != b.generate((0, 0, 0)).serialize());
+    }
+    
+    #[test]
+    fn caves_below_surface_are_dark() {
+        let blocks = WorldGen::new().with_caves(0., DEFAULT_CAVE_FREQUENCY).generate((0, -1, 0));
+        
+        let mut caves = blocks.into_iter().filter(|&(_, b)| b.is_air()).peekable();
+        assert!(caves.peek().is_some());
+        assert!(caves.all(|(c, _)| blocks.light_at(c) == 0));
+    }
+    
+    // The number of air blocks in the limestone sectors of a region.
+    fn limestone_air(region: &HashMap<(i32, i32, i32), BlockList>) -> usize {
+        region.iter()
+              .filter(|&(pos, _)| pos.1 == -1)
+              .map(|(_, blocks)| blocks.into_iter().filter(|&(_, b)| b.is_air()).count())
+              .sum()
+    }
+    
+    #[test]
+    fn high_cave_threshold_carves_nothing() {
+        let gen = WorldGen::new().with_caves(1.5, DEFAULT_CAVE_FREQUENCY);
+        
+        assert_eq!(limestone_air(&generate_region(&gen, (0, -1, 0), 1)), 0);
+    }
+    
+    #[test]
+    fn low_cave_threshold_carves_caves() {
+        let gen = WorldGen::new().with_caves(0., DEFAULT_CAVE_FREQUENCY);
+        
+        assert!(limestone_air(&generate_region(&gen, (0, -1, 0), 1)) > 0);
+    }
+    
+    #[test]
+    fn caves_never_open_under_water() {
+        let gen = WorldGen::new().with_sea_level(SECTOR_SIZE)
+                                 .with_caves(-1., DEFAULT_CAVE_FREQUENCY);
+        let blocks = gen.generate((0, 0, 0));
+        
+        for (coords, block) in &blocks {
+            if !block.is_air() {
+                continue;
+            }
+            
+            let (x, z) = (coords.x(), coords.z());
+            for y in coords.y()..SECTOR_SIZE as u8 {
+                assert!(*blocks.get(SectorSpaceCoords::new(x, y, z)) != Block::Water,
+                        "water over a cave at {:?}", coords);
+            }
+        }
+    }
+    
+    fn coal(threshold: f32, max_y: i32) -> OreConfig {
+        OreConfig { block: Block::CoalOre, frequency: 0.15, threshold, max_y }
+    }
+    
+    #[test]
+    fn high_ore_threshold_places_no_ore() {
+        let gen = WorldGen::new().with_ores(vec![coal(2., -1)]);
+        let blocks = gen.generate((0, -1, 0));
+        
+        assert!(blocks.into_iter().all(|(_, b)| *b != Block::CoalOre));
+    }
+    
+    #[test]
+    fn ore_only_replaces_limestone() {
+        // Without caves, so that the surface and limestone
+        // sectors have plenty of other blocks beside limestone.
+        let plain = WorldGen::new().with_caves(2., DEFAULT_CAVE_FREQUENCY).with_ores(Vec::new());
+        let ores = plain.clone().with_ores(vec![coal(-2., 100)]);
+        
+        for &sector in &[(0, 0, 0), (0, -1, 0), (0, -2, 0)] {
+            let (before, after) = (plain.generate(sector), ores.generate(sector));
+            
+            for ((coords, a), (_, b)) in before.into_iter().zip(&after) {
+                if *a == Block::Limestone {
+                    assert_eq!(*b, Block::CoalOre, "at {:?}", coords);
+                } else {
+                    assert_eq!(a, b, "at {:?}", coords);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn ore_stays_below_max_y() {
+        let gen = WorldGen::new().with_ores(vec![coal(-2., -12)]);
+        let blocks = gen.generate((0, -1, 0));
+        
+        for (coords, block) in &blocks {
+            if *block == Block::CoalOre {
+                assert!(coords.y() as i32 - SECTOR_SIZE as i32 <= -12);
+            }
+        }
+    }
+    
+    #[test]
+    fn height_amplitude_blends_across_biome_borders() {
+        let gen = WorldGen::with_seed(3);
+        let mut last = gen.height_amplitude_at(0, 0);
+        
+        for x in 1..5000 {
+            let amplitude = gen.height_amplitude_at(x, 0);
+            assert!((amplitude - last).abs() < 2., "jump at x = {}", x);
+            last = amplitude;
+            
+            // Away from the borders, it is the biome's own.
+            let value = gen.biome_value(x, 0);
+            if (value - DESERT_BELOW).abs() > BIOME_BLEND &&
+               (value - MOUNTAINS_ABOVE).abs() > BIOME_BLEND {
+                assert_eq!(amplitude, gen.biome_at(x, 0).height_amplitude());
+            }
+        }
+    }
+    
+    #[test]
+    fn biome_surface_matches_across_sectors() {
+        // No caves, water or trees, so the top block
+        // of every column is the biome's surface.
+        let gen = WorldGen::with_seed(5).with_caves(2., DEFAULT_CAVE_FREQUENCY)
+                                        .with_sea_level(0);
+        
+        for &sector in &[(0, 0, 0), (1, 0, 0), (-1, 0, -1)] {
+            let blocks = gen.generate(sector);
+            
+            for x in 0..SECTOR_SIZE as u8 {
+                for z in 0..SECTOR_SIZE as u8 {
+                    let top = (0..SECTOR_SIZE as u8).rev()
+                                                    .map(|y| *blocks.get(SectorSpaceCoords::new(x, y, z)))
+                                                    .find(|b| !b.is_air());
+                    
+                    let biome = gen.biome_at(x as i32 + SECTOR_SIZE as i32 * sector.0,
+                                             z as i32 + SECTOR_SIZE as i32 * sector.2);
+                    match top {
+                        Some(Block::Tree) | Some(Block::Leaves) | None => {},
+                        Some(block) => assert_eq!(block, biome.surface_block()),
+                    }
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn generate_region_covers_cube() {
+        let gen = WorldGen::new();
+        
+        assert_eq!(generate_region(&gen, (4, 0, -2), 1).len(), 27);
+        assert_eq!(generate_region(&gen, (0, 0, 0), 0).len(), 1);
+        assert!(generate_region(&gen, (0, 0, 0), -1).is_empty());
+    }
+    
+    #[test]
+    fn generate_region_contents() {
+        let gen = WorldGen::new();
+        let region = generate_region(&gen, (0, 0, 0), 1);
+        
+        for x in -1..2 {
+            for z in -1..2 {
+                assert_eq!(region[&(x, 1, z)].solid_count(), 0);
+                assert_eq!(region[&(x, 0, z)].serialize(), gen.generate((x, 0, z)).serialize());
+            }
+        }
+        
+        let deep = generate_region(&gen, (0, -3, 0), 1);
+        for blocks in deep.values() {
+            assert!(blocks.into_iter().all(|(_, b)| *b == Block::Bedrock));
+        }
+    }
 }
@@ -1,54 +1,312 @@
 //! The main entry point.
 
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 use glfw::CursorMode;
-use luminance::framebuffer::Framebuffer;
+use luminance::framebuffer::{ColorSlot, DepthSlot, Framebuffer};
+use luminance::pipeline::{entry, pipeline, Gpu, ShadingGate};
 use luminance::texture::{Dim2, Flat};
-use luminance_glfw::{Action, Device, GLFWDevice, GLFWDeviceError, Key,
+use luminance_glfw::{Action, Device, GLFWDevice, GLFWDeviceError, Key, MouseButton,
                      WindowDim, WindowOpt, WindowEvent};
-use camera::{Camera, MovementDirection};
+use blit::ScaledTarget;
+use camera::{self, Camera, MovementDirection};
+use hud::Hud;
+use maths::{self, Aabb, ToMatrix, Translation};
 use model::Drawable;
 use resources::Resources;
-use terrain::Terrain;
+use shader;
+use sky::Sky;
+use skybox::Skybox;
+use terrain::{self, Block, CollisionResult, DebugTriangle, Terrain, WorldGen,
+              DEFAULT_STEP_HEIGHT, SECTOR_SIZE};
 
 const SCREEN_SIZE: (u32, u32) = (800, 800);
+const TITLE: &str = "luminance_basic";
 const SPEED: f32 = 15.;
 const FAST_MULTIPLIER: f32 = 5.;
 const SENSITIVITY: f32 = 0.1;
+const TICK_RATE: f32 = 20.;
+const TICK_DELTA: f32 = 1. / TICK_RATE;
+const MAX_TICKS_PER_FRAME: u32 = 5;
+const RENDER_SCALES: [f32; 3] = [1., 0.5, 0.25];
+const PREGENERATE_TIMEOUT_SECS: u64 = 10;
+const REACH: f32 = 8.;
+const PLACE_BLOCK: Block = Block::Limestone;
+const GRAVITY: f32 = 30.;
+const JUMP_SPEED: f32 = 9.;
+const MAX_FALL_SPEED: f32 = 50.;
+const FRAME_SAMPLES: usize = 60;
+const TITLE_INTERVAL: f32 = 0.5;
+const ZOOM: f32 = 4.;
+
+/// What the `Viewer` draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerrainKind {
+    /// The streamed voxel world.
+    Voxel,
+    
+    /// A single textured triangle, drawn with the terrain shaders.
+    /// For debugging only: it rules out meshing and streaming when
+    /// tracking down shader or camera bugs.
+    Triangle,
+}
+
+/// Settings for the `Viewer`.
+#[derive(Clone, Debug)]
+pub struct ViewerOptions {
+    /// If set, the camera is kept inside this box, in world coordinates.
+    pub bounds: Option<Aabb>,
+    
+    /// The fraction of the window resolution the terrain is rendered
+    /// at before being stretched to fit. Lower is faster but blurrier.
+    /// `F3` cycles through some presets at runtime.
+    pub render_scale: f32,
+    
+    /// Always render through an offscreen target, so that the depth
+    /// buffer can be read back by `Viewer::pick_at_cursor`.
+    pub depth_picking: bool,
+    
+    /// Capture the cursor for mouse look as soon as the window opens.
+    /// `Tab` toggles the capture, and it is released when the window
+    /// loses focus.
+    pub capture_cursor_on_start: bool,
+    
+    /// Wait for the sectors around the spawn point to be generated
+    /// before the first frame, so the world does not start empty.
+    /// The wait gives up after a few seconds.
+    pub pregenerate_spawn: bool,
+    
+    /// What to draw. See `TerrainKind`.
+    pub terrain_kind: TerrainKind,
+    
+    /// The seed of the generated world. Without one, the world
+    /// is always the same. See `WorldGen::with_seed`.
+    pub seed: Option<u32>,
+    
+    /// How far the camera turns per unit of mouse movement.
+    pub mouse_sensitivity: f32,
+    
+    /// Look down when the mouse moves up, and up when it moves down.
+    pub invert_y: bool,
+    
+    /// The vertical field of view, **in radians**. Holding `C`
+    /// zooms in by narrowing it. See `Camera::set_fov`.
+    pub fov: f32,
+    
+    /// How fast the camera moves, in blocks per second.
+    pub move_speed: f32,
+    
+    /// What `move_speed` is multiplied by while `E` is held.
+    pub fast_multiplier: f32,
+    
+    /// Start in walk mode, where the camera falls under gravity and
+    /// `Space` jumps, rather than flying. `F` toggles between them.
+    /// Only the voxel world can be walked on.
+    pub walk: bool,
+    
+    /// The number of threads generating sectors.
+    pub workers: usize,
+    
+    /// Draw the textured `Skybox` behind the terrain,
+    /// rather than the `Sky` gradient and sun.
+    pub skybox: bool,
+}
+
+impl Default for ViewerOptions {
+    fn default() -> ViewerOptions {
+        ViewerOptions {
+            bounds: None,
+            render_scale: 1.,
+            depth_picking: false,
+            capture_cursor_on_start: true,
+            pregenerate_spawn: false,
+            terrain_kind: TerrainKind::Voxel,
+            seed: None,
+            mouse_sensitivity: SENSITIVITY,
+            invert_y: false,
+            fov: camera::DEFAULT_FOV,
+            move_speed: SPEED,
+            fast_multiplier: FAST_MULTIPLIER,
+            walk: false,
+            workers: terrain::DEFAULT_WORKERS,
+            skybox: false,
+        }
+    }
+}
 
 /// The core of the app, manages the program.
 pub struct Viewer {
     device: GLFWDevice,
     render_target: Framebuffer<Flat, Dim2, (), ()>,
+    window_size: (u32, u32),
     camera: Camera,
+    scaled_target: Option<ScaledTarget>,
+    hud: Option<Hud>,
+    cursor_captured: bool,
+    clicks: Vec<MouseButton>,
+    reload_shaders: bool,
+    toggle_wireframe: bool,
+    walking: bool,
+    fall_speed: f32,
+    grounded: bool,
+    frame_times: FrameTimes,
+    title_timer: f32,
+    options: ViewerOptions,
 }
 
 impl Viewer {
     /// Start up!
     pub fn run() {
+        Self::run_with(ViewerOptions::default());
+    }
+    
+    /// Start up with these options.
+    pub fn run_with(options: ViewerOptions) {
         let device = Self::create_device().unwrap();
+        let render_scale = options.render_scale;
+        let walking = options.walk && options.terrain_kind == TerrainKind::Voxel;
         
-        Viewer {
+        let mut viewer = Viewer {
             device,
             render_target: Framebuffer::default([SCREEN_SIZE.0, SCREEN_SIZE.1]),
+            window_size: SCREEN_SIZE,
             camera: Camera::new(SCREEN_SIZE),
-        }.start();
+            scaled_target: None,
+            hud: None,
+            cursor_captured: false,
+            clicks: Vec::new(),
+            reload_shaders: false,
+            toggle_wireframe: false,
+            walking,
+            fall_speed: 0.,
+            grounded: false,
+            frame_times: FrameTimes::new(),
+            title_timer: 0.,
+            options,
+        };
+        
+        let fov = viewer.options.fov;
+        viewer.camera.set_fov(fov);
+        
+        viewer.set_render_scale(render_scale);
+        viewer.start();
+    }
+    
+    /// Change the fraction of the window resolution that
+    /// the terrain is rendered at. `1` renders directly
+    /// to the window, unless depth picking is enabled.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.options.render_scale = scale;
+        
+        if scale >= 1. && !self.options.depth_picking {
+            self.scaled_target = None;
+        } else if let Some(ref mut target) = self.scaled_target {
+            target.set_scale(self.window_size, scale);
+        } else {
+            self.scaled_target = Some(ScaledTarget::new(self.window_size, scale));
+        }
+    }
+    
+    // Recreate the render targets and fix the camera's
+    // aspect ratio after the framebuffer is resized.
+    fn resize(&mut self, width: u32, height: u32) {
+        // A minimized window reports a zero size.
+        if width == 0 || height == 0 || (width, height) == self.window_size {
+            return;
+        }
+        
+        self.window_size = (width, height);
+        self.render_target = Framebuffer::default([width, height]);
+        self.camera.set_aspect(width, height);
+        
+        if let Some(ref mut hud) = self.hud {
+            hud.set_window_size(self.window_size);
+        }
+        
+        let scale = self.options.render_scale;
+        self.set_render_scale(scale);
+    }
+    
+    /// Find the point under the cursor by reading back the depth
+    /// buffer and unprojecting it. The cursor is captured, so this
+    /// is the center of the screen.
+    ///
+    /// The point is relative to the terrain origin, like the camera
+    /// translation. Returns `None` if depth picking is disabled or
+    /// nothing is under the cursor.
+    pub fn pick_at_cursor(&self) -> Option<Translation> {
+        if !self.options.depth_picking {
+            return None;
+        }
+        
+        let depth = match self.scaled_target {
+            Some(ref target) => target.depth_at_center(),
+            None => return None,
+        };
+        
+        if depth >= 1. {
+            return None;
+        }
+        
+        let view_proj = maths::matrix_mul(self.camera.projection_matrix(),
+                                          &self.camera.to_matrix());
+        let inverse = maths::matrix_inverse(&view_proj)?;
+        
+        // Screen center in normalized device coordinates.
+        let (x, y, z) = maths::transform_point(&inverse, (0., 0., depth * 2. - 1.));
+        
+        Some(Translation::new(x, y, z))
+    }
+    
+    // The average time per frame, in seconds,
+    // over the last few frames.
+    fn frame_time(&self) -> f32 {
+        self.frame_times.average()
     }
     
     fn create_device() -> Result<GLFWDevice, GLFWDeviceError> {
         GLFWDevice::new(WindowDim::Windowed(SCREEN_SIZE.0, SCREEN_SIZE.1),
-                        "luminance_basic",
+                        TITLE,
                         WindowOpt::default())
     }
     
     fn start(mut self) {        
         let resources = Resources::new();
         
-        self.device.lib_handle_mut().set_cursor_mode(CursorMode::Disabled);
+        let capture = self.options.capture_cursor_on_start;
+        self.set_cursor_captured(capture);
+        
+        if self.options.terrain_kind == TerrainKind::Triangle {
+            self.start_triangle(&resources);
+            return;
+        }
         
         self.camera.translation_mut().y = 50.;
         
-        let mut terrain = Terrain::new(&resources);
+        let gen = match self.options.seed {
+            Some(seed) => WorldGen::with_seed(seed),
+            None => WorldGen::new(),
+        };
+        let mut terrain = match Terrain::try_with_generator(&resources, shader::TERRAIN,
+                                                            gen, self.options.workers) {
+            Ok(terrain) => terrain,
+            Err(e) => {
+                eprintln!("failed to load the terrain shaders: {:?}", e);
+                return;
+            },
+        };
+        let sky = Sky::new(shader::SKY);
+        terrain.set_sun_direction(sky.sun_direction());
+        let skybox = if self.options.skybox {
+            Some(Skybox::new(&resources, shader::SKYBOX))
+        } else {
+            None
+        };
+        self.hud = Some(Hud::new(&resources, shader::HUD, self.window_size));
+        
+        if self.options.pregenerate_spawn && !self.pregenerate_spawn(&mut terrain) {
+            return;
+        }
         
         /*
         let test1 = mat4! [
@@ -71,6 +329,7 @@ impl Viewer {
         */
         
         let mut delta = 0.;
+        let mut tick_time = 0.;
         loop {
             //println!("{:?}", terrain.collide(self.camera.translation_mut()));
             let begin = Instant::now();
@@ -79,26 +338,154 @@ impl Viewer {
                 break;
             }
             self.handle_realtime_input(delta);
+            self.clamp_to_bounds(terrain.origin());
+            
+            terrain.update(&mut self.camera);
+            self.edit_terrain(&mut terrain);
             
-            terrain.update(&self.camera);
+            if self.reload_shaders {
+                self.reload_shaders = false;
+                
+                if let Err(e) = terrain.reload_shaders() {
+                    eprintln!("failed to reload shaders: {:?}", e);
+                }
+            }
+            
+            if self.toggle_wireframe {
+                self.toggle_wireframe = false;
+                
+                let wireframe = !terrain.wireframe();
+                terrain.set_wireframe(wireframe);
+            }
+            
+            // Run the simulation at a fixed rate. After a long stall,
+            // drop the backlog rather than trying to catch up.
+            tick_time += delta;
+            let mut ticks = 0;
+            while tick_time >= TICK_DELTA {
+                if ticks == MAX_TICKS_PER_FRAME {
+                    tick_time = 0.;
+                    break;
+                }
+                
+                terrain.tick(TICK_DELTA);
+                tick_time -= TICK_DELTA;
+                ticks += 1;
+            }
             
-            terrain.collide(self.camera.translation_mut());
+            let collision = terrain.collide(self.camera.translation_mut(), DEFAULT_STEP_HEIGHT);
+            let sector = terrain.current_sector(&self.camera);
+            let loaded = terrain.region_ready((sector.0, sector.1 - 1, sector.2), sector);
+            self.land(collision, loaded);
             
-            terrain.draw(&mut self.device, &self.render_target, &self.camera);
+            let window = &self.render_target;
+            let camera = &self.camera;
+            let hud = self.hud.as_ref();
+            let skybox = skybox.as_ref();
+            match self.scaled_target {
+                Some(ref target) => {
+                    self.device.draw(|| {
+                        render_scene(target.framebuffer(), &sky, skybox, &terrain, None, camera);
+                        target.blit_with(window, |gpu, shade_gate| {
+                            draw_hud(gpu, shade_gate, hud, camera);
+                        });
+                    });
+                },
+                None => self.device.draw(|| render_scene(window, &sky, skybox, &terrain, hud, camera)),
+            }
             
             let delta_dur = Instant::now() - begin;          
             delta = delta_dur.as_secs() as f32
                     + delta_dur.subsec_nanos() as f32 * 1e-9;
+            self.record_frame(delta);
             //println!("delta: {:?}", delta);
             
             //::std::thread::sleep(::std::time::Duration::from_millis(10));
         }
     }
     
+    // A minimal main loop that only draws the debug triangle.
+    fn start_triangle(&mut self, resources: &Resources) {
+        let triangle = DebugTriangle::new(resources, shader::TERRAIN);
+        
+        let mut delta = 0.;
+        while self.handle_events() {
+            let begin = Instant::now();
+            
+            self.handle_realtime_input(delta);
+            
+            match self.scaled_target {
+                Some(ref target) => {
+                    let window = &self.render_target;
+                    let camera = &self.camera;
+                    
+                    self.device.draw(|| {
+                        triangle.render(target.framebuffer(), camera);
+                        target.blit(window);
+                    });
+                },
+                None => triangle.draw(&mut self.device, &self.render_target, &self.camera),
+            }
+            
+            let delta_dur = Instant::now() - begin;
+            delta = delta_dur.as_secs() as f32
+                    + delta_dur.subsec_nanos() as f32 * 1e-9;
+            self.record_frame(delta);
+        }
+    }
+    
+    // Add a frame to the average frame time, and show
+    // the average in the window title every so often.
+    fn record_frame(&mut self, delta: f32) {
+        self.frame_times.push(delta);
+        
+        self.title_timer += delta;
+        if self.title_timer < TITLE_INTERVAL {
+            return;
+        }
+        self.title_timer = 0.;
+        
+        let average = self.frame_time();
+        let fps = if average > 0. { 1. / average } else { 0. };
+        let title = format!("{} - {:.2} ms ({:.0} fps)", TITLE, average * 1000., fps);
+        self.device.lib_handle_mut().set_title(&title);
+    }
+    
+    // Update the terrain until the sectors around the camera are
+    // generated, or the timeout passes. Returns false if the window
+    // was closed while waiting.
+    fn pregenerate_spawn(&mut self, terrain: &mut Terrain) -> bool {
+        let begin = Instant::now();
+        
+        loop {
+            if !self.handle_events() {
+                return false;
+            }
+            
+            terrain.update(&mut self.camera);
+            
+            let spawn = terrain.current_sector(&self.camera);
+            if terrain.region_ready((spawn.0 - 1, spawn.1 - 2, spawn.2 - 1),
+                                    (spawn.0 + 1, spawn.1, spawn.2 + 1)) {
+                return true;
+            }
+            
+            if Instant::now() - begin > Duration::from_secs(PREGENERATE_TIMEOUT_SECS) {
+                eprintln!("gave up waiting for the spawn area to generate");
+                return true;
+            }
+            
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    
     // #Return Value
     // Wheather the game should keep running
     fn handle_events(&mut self) -> bool {
         let mut keep_running = true;
+        let mut next_scale = false;
+        let mut capture = None;
+        let mut size = None;
         
         for ev in self.device.events() {
             match ev {
@@ -107,87 +494,321 @@ impl Viewer {
                         keep_running = false;
                         break;
                     },
+                WindowEvent::Key(Key::F3, _, Action::Press, _) => next_scale = true,
+                WindowEvent::Key(Key::R, _, Action::Press, _) => self.reload_shaders = true,
+                WindowEvent::Key(Key::F2, _, Action::Press, _) => self.toggle_wireframe = true,
+                WindowEvent::Key(Key::F, _, Action::Press, _)
+                    if self.options.terrain_kind == TerrainKind::Voxel
+                    => {
+                        self.walking = !self.walking;
+                        self.fall_speed = 0.;
+                    },
+                WindowEvent::Key(Key::Tab, _, Action::Press, _) =>
+                    capture = Some(!capture.unwrap_or(self.cursor_captured)),
+                WindowEvent::Focus(false) => capture = Some(false),
+                WindowEvent::FramebufferSize(w, h) => size = Some((w, h)),
+                WindowEvent::MouseButton(button, Action::Press, _)
+                    if self.cursor_captured && self.options.terrain_kind == TerrainKind::Voxel
+                    => self.clicks.push(button),
                 _ => {},
             }
         }
         
+        if let Some(capture) = capture {
+            self.set_cursor_captured(capture);
+        }
+        
+        if let Some((w, h)) = size {
+            self.resize(w.max(0) as u32, h.max(0) as u32);
+        }
+        
+        if next_scale {
+            let current = self.options.render_scale;
+            let i = RENDER_SCALES.iter().position(|&s| s == current).map_or(0, |i| i + 1);
+            self.set_render_scale(RENDER_SCALES[i % RENDER_SCALES.len()]);
+        }
+        
         keep_running
     }
     
+    // Break the block the camera is looking at with the left mouse
+    // button, or place one against it with the right.
+    fn edit_terrain(&mut self, terrain: &mut Terrain) {
+        for button in self.clicks.drain(..) {
+            let hit = match terrain.raycast(self.camera.translation(), self.camera.forward(), REACH) {
+                Some(h) => h,
+                None => continue,
+            };
+            
+            match button {
+                // Left
+                MouseButton::Button1 => {
+                    let old = terrain.block_at(hit.pos);
+                    if terrain.set_block(hit.pos, Block::Air) {
+                        if let Some(old) = old {
+                            terrain.spawn_break_particles(hit.pos, old);
+                        }
+                    }
+                },
+                // Right
+                MouseButton::Button2 => {
+                    let pos = (hit.pos.0 + hit.normal.0,
+                               hit.pos.1 + hit.normal.1,
+                               hit.pos.2 + hit.normal.2);
+                    
+                    if hit.normal != (0, 0, 0) && terrain.block_at(pos) == Some(Block::Air) {
+                        terrain.set_block(pos, PLACE_BLOCK);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    
+    // Stop falling on landing, and stop rising on hitting a ceiling.
+    // Until the sectors around the camera are generated, there may be
+    // nothing to land on, so the camera hovers instead.
+    fn land(&mut self, collision: CollisionResult, loaded: bool) {
+        self.grounded = collision.grounded;
+        
+        if !loaded ||
+           (collision.grounded && self.fall_speed < 0.) ||
+           (collision.hit_ceiling && self.fall_speed > 0.) {
+            self.fall_speed = 0.;
+        }
+    }
+    
+    // Capture or release the cursor. While captured, the
+    // cursor is hidden and moving the mouse looks around.
+    fn set_cursor_captured(&mut self, captured: bool) {
+        let mode = if captured {
+            CursorMode::Disabled
+        } else {
+            CursorMode::Normal
+        };
+        
+        self.device.lib_handle_mut().set_cursor_mode(mode);
+        if captured {
+            // Avoid a jump from wherever the cursor was.
+            self.device.lib_handle_mut().set_cursor_pos(0., 0.);
+        }
+        
+        self.cursor_captured = captured;
+    }
+    
+    // Keep the camera inside the bounds, if any. The bounds are in
+    // world coordinates, so they are shifted by the terrain origin.
+    fn clamp_to_bounds(&mut self, origin: (i32, i32, i32)) {
+        if let Some(ref bounds) = self.options.bounds {
+            let size = SECTOR_SIZE as i32;
+            let offset = ((origin.0 * size) as f32,
+                          (origin.1 * size) as f32,
+                          (origin.2 * size) as f32);
+            
+            let local = Aabb::new(Translation::new(bounds.min.x - offset.0,
+                                                   bounds.min.y - offset.1,
+                                                   bounds.min.z - offset.2),
+                                  Translation::new(bounds.max.x - offset.0,
+                                                   bounds.max.y - offset.1,
+                                                   bounds.max.z - offset.2));
+            
+            local.clamp(self.camera.translation_mut());
+        }
+    }
+    
     fn handle_realtime_input(&mut self, delta: f32) {
+        let fov = match self.device.lib_handle().get_key(Key::C) {
+            Action::Press | Action::Repeat => self.options.fov / ZOOM,
+            Action::Release => self.options.fov,
+        };
+        self.camera.set_fov(fov);
+        
         let multi = match self.device.lib_handle().get_key(Key::E) {
-            Action::Press | Action::Repeat => FAST_MULTIPLIER,
+            Action::Press | Action::Repeat => self.options.fast_multiplier,
             Action::Release => 1.,
         };
+        let speed = self.options.move_speed;
         
         match self.device.lib_handle().get_key(Key::W) {
             Action::Press | Action::Repeat =>
-                self.camera.move_dir(MovementDirection::Forward, SPEED * delta * multi),
+                self.camera.move_dir(MovementDirection::Forward, speed * delta * multi),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::S) {
             Action::Press | Action::Repeat =>
-                self.camera.move_dir(MovementDirection::Backward, SPEED * delta * multi),
+                self.camera.move_dir(MovementDirection::Backward, speed * delta * multi),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::A) {
             Action::Press | Action::Repeat =>
-                self.camera.move_dir(MovementDirection::Left, SPEED * delta * multi),
+                self.camera.move_dir(MovementDirection::Left, speed * delta * multi),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::D) {
             Action::Press | Action::Repeat =>
-                self.camera.move_dir(MovementDirection::Right, SPEED * delta * multi),
+                self.camera.move_dir(MovementDirection::Right, speed * delta * multi),
             Action::Release => {},
         }
         
-        match self.device.lib_handle().get_key(Key::Space) {
-            Action::Press | Action::Repeat =>
-                self.camera.translation_mut().slide(0., SPEED * delta * multi, 0.),
-            Action::Release => {},
-        }
-        
-        match self.device.lib_handle().get_key(Key::LeftShift) {
-            Action::Press | Action::Repeat =>
-                self.camera.translation_mut().slide(0., -SPEED * delta * multi, 0.),
-            Action::Release => {},
+        if self.walking {
+            match self.device.lib_handle().get_key(Key::Space) {
+                Action::Press | Action::Repeat if self.grounded => {
+                    self.fall_speed = JUMP_SPEED;
+                    self.grounded = false;
+                },
+                _ => {},
+            }
+            
+            // Move, then accelerate, so a camera that `land` holds
+            // still does not creep downward.
+            self.camera.translation_mut().slide(0., self.fall_speed * delta, 0.);
+            self.fall_speed = (self.fall_speed - GRAVITY * delta).max(-MAX_FALL_SPEED);
+        } else {
+            match self.device.lib_handle().get_key(Key::Space) {
+                Action::Press | Action::Repeat =>
+                    self.camera.translation_mut().slide(0., speed * delta * multi, 0.),
+                Action::Release => {},
+            }
+            
+            match self.device.lib_handle().get_key(Key::LeftShift) {
+                Action::Press | Action::Repeat =>
+                    self.camera.translation_mut().slide(0., -speed * delta * multi, 0.),
+                Action::Release => {},
+            }
         }
         
         match self.device.lib_handle().get_key(Key::Up) {
             Action::Press | Action::Repeat =>
-                self.camera.rotation_mut().spin(SPEED * delta, 0.),
+                self.camera.spin(SPEED * delta, 0.),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::Down) {
             Action::Press | Action::Repeat =>
-                self.camera.rotation_mut().spin(-SPEED * delta, 0.),
+                self.camera.spin(-SPEED * delta, 0.),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::Left) {
             Action::Press | Action::Repeat =>
-                self.camera.rotation_mut().spin(0., SPEED * delta),
+                self.camera.spin(0., SPEED * delta),
             Action::Release => {},
         }
         
         match self.device.lib_handle().get_key(Key::Right) {
             Action::Press | Action::Repeat =>
-                self.camera.rotation_mut().spin(0., -SPEED * delta),
+                self.camera.spin(0., -SPEED * delta),
             Action::Release => {},
         }
         
         //println!("self.camera: {:?}", self.camera.to_matrix());
         //println!("self.camera rotation: {:?}", self.camera.rotation());
         
+        if !self.cursor_captured {
+            return;
+        }
+        
         //println!("mouse pos: {:?}", self.device.lib_handle().get_cursor_pos());
         let mouse_pos = self.device.lib_handle().get_cursor_pos();
         let mouse_pos = (mouse_pos.0 as f32, mouse_pos.1 as f32);
-        self.camera.rotation_mut().spin(delta * -mouse_pos.1 * SENSITIVITY,
-                                        delta * -mouse_pos.0 * SENSITIVITY);
+        let sensitivity = self.options.mouse_sensitivity;
+        let pitch = if self.options.invert_y { mouse_pos.1 } else { -mouse_pos.1 };
+        self.camera.spin(delta * pitch * sensitivity,
+                         delta * -mouse_pos.0 * sensitivity);
         self.device.lib_handle_mut().set_cursor_pos(0., 0.);
     }
 }
+
+// A rolling average of the last `FRAME_SAMPLES` frame times,
+// kept in a fixed ring buffer.
+struct FrameTimes {
+    samples: [f32; FRAME_SAMPLES],
+    next: usize,
+    count: usize,
+}
+
+impl FrameTimes {
+    fn new() -> FrameTimes {
+        FrameTimes {
+            samples: [0.; FRAME_SAMPLES],
+            next: 0,
+            count: 0,
+        }
+    }
+    
+    // Add a frame time, in seconds, replacing the oldest once full.
+    fn push(&mut self, delta: f32) {
+        self.samples[self.next] = delta;
+        self.next = (self.next + 1) % FRAME_SAMPLES;
+        self.count = (self.count + 1).min(FRAME_SAMPLES);
+    }
+    
+    // The mean of the recorded frame times, or `0` if there are none.
+    fn average(&self) -> f32 {
+        if self.count == 0 {
+            return 0.;
+        }
+        
+        self.samples[..self.count].iter().sum::<f32>() / self.count as f32
+    }
+}
+
+// Draw every pass of the scene in a single pipeline, so
+// that they share the target's depth buffer. The skybox,
+// if any, is drawn instead of the sky. The HUD, if any,
+// is drawn last.
+fn render_scene<CS, DS>(render_target: &Framebuffer<Flat, Dim2, CS, DS>,
+                        sky: &Sky,
+                        skybox: Option<&Skybox>,
+                        terrain: &Terrain,
+                        hud: Option<&Hud>,
+                        camera: &Camera)
+    where CS: ColorSlot<Flat, Dim2>, DS: DepthSlot<Flat, Dim2> {
+    entry(|gpu| {
+        pipeline(render_target, terrain::CLEAR_COLOR, |shade_gate| {
+            match skybox {
+                Some(skybox) => skybox.draw_in(&gpu, &shade_gate, camera),
+                None => sky.draw_in(&gpu, &shade_gate, camera),
+            }
+            terrain.draw_in(&gpu, &shade_gate, camera);
+            draw_hud(&gpu, &shade_gate, hud, camera);
+        });
+    });
+}
+
+// Draw the HUD over whatever is already in the pipeline.
+fn draw_hud(gpu: &Gpu, shade_gate: &ShadingGate, hud: Option<&Hud>, camera: &Camera) {
+    if let Some(hud) = hud {
+        hud.draw_in(gpu, shade_gate, camera);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn frame_times_average_recorded_frames() {
+        let mut times = FrameTimes::new();
+        assert_eq!(times.average(), 0.);
+        
+        times.push(1.);
+        times.push(3.);
+        assert_eq!(times.average(), 2.);
+    }
+    
+    #[test]
+    fn frame_times_replace_oldest_when_full() {
+        let mut times = FrameTimes::new();
+        
+        times.push(100.);
+        for _ in 0..FRAME_SAMPLES {
+            times.push(2.);
+        }
+        
+        assert_eq!(times.average(), 2.);
+    }
+}